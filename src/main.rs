@@ -1,8 +1,18 @@
+// Bevy systems take their data as parameters, so long signatures and nested query types are expected
+#![allow(clippy::too_many_arguments, clippy::type_complexity)]
+
 use bevy::prelude::*;
 use bevy::input::mouse::MouseMotion;
+use bevy::render::mesh::VertexAttributeValues;
+use bevy::window::{MonitorSelection, PresentMode, WindowMode};
 use bevy_egui::{egui, EguiContexts, EguiPlugin};
-use rand::Rng;
-use std::collections::HashMap;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::Write as _;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
 
 // Constants
 const PLAYER_SPEED: f32 = 5.0;
@@ -11,6 +21,100 @@ const BLOCK_SIZE: f32 = 1.0;
 const WORLD_SIZE: i32 = 20;
 const WORLD_HEIGHT: i32 = 10;
 const MOUSE_SENSITIVITY: f32 = 0.002;
+const EYE_OFFSET: Vec3 = Vec3::new(0.0, 0.7, 0.0); // Eyes sit slightly above the player center
+const THIRD_PERSON_OFFSET: Vec3 = Vec3::new(0.6, 0.3, 4.0); // Right, up and back from the eyes
+const CAMERA_COLLISION_MARGIN: f32 = 0.2;
+const FLUID_MAX_LEVEL: u8 = 7; // Thinnest flowing water, it spreads no further
+const BLOCK_TICK_INTERVAL: f32 = 0.05; // Twenty block ticks a second
+const MAX_BLOCK_TICKS_PER_FRAME: u32 = 4;
+const MAX_SCHEDULED_TICKS: usize = 64; // Scheduled block updates handled per block tick
+const FLUID_TICK_DELAY: u64 = 5;
+const FALL_TICK_DELAY: u64 = 2;
+const CHUNK_SIZE: i32 = 16; // Columns are grouped into chunks for random ticks
+const RANDOM_TICKS_PER_CHUNK: u32 = 3;
+const ARM_OFFSET: Vec3 = Vec3::new(0.3, -0.25, -0.45); // Lower right of the view, relative to the eyes
+const FREE_CAMERA_SPEED: f32 = 10.0;
+const RENDER_DISTANCE_SCALE: f32 = 16.0; // Blocks per step of render distance
+const GRAVITY: f32 = -9.8;
+const PLAYER_JUMP_VELOCITY: f32 = 5.0;
+const PLAYER_MINING_TIME_SCALE: f32 = 0.3; // Survival mining time per point of hardness
+const BODY_HEIGHT: i32 = 2; // Player and Crim occupy two stacked cells
+const CRIM_BREAK_DELAY: f32 = 1.0; // Wind-up before Crim starts damaging a block
+const CRIM_SPAWN_INTERVAL: f32 = 10.0;
+const CRIM_SPAWN_ATTEMPTS: usize = 20;
+const CRIM_SPAWN_MIN_DISTANCE: f32 = 12.0;
+const CRIM_SPAWN_MAX_DISTANCE: f32 = 24.0;
+const CRIM_SPAWN_VIEW_COS: f32 = 0.5; // Roughly a 120 degree view cone
+const CRIM_DESPAWN_DISTANCE: f32 = 40.0;
+const CRIM_VISION_RANGE: f32 = 15.0;
+const CRIM_VISION_COS: f32 = 0.3; // Crim sees about 145 degrees in front of it
+const CRIM_BREAK_IN_RANGE: f32 = 12.0; // How close Crim must be to go after a sealed shelter
+const CRIM_REACH: f32 = 2.0;
+const MINING_NOISE_RADIUS: f32 = 10.0;
+const PLACING_NOISE_RADIUS: f32 = 6.0;
+const CRIM_ATTACK_RANGE: f32 = 1.5;
+const CRIM_ATTACK_DAMAGE: f32 = 25.0; // Health per second while Crim is in reach
+const WATER_GRAVITY_SCALE: f32 = 0.3;
+const WATER_BUOYANCY: f32 = 2.5; // Upward push once the head is under, almost floats
+const WATER_MAX_SINK_SPEED: f32 = 2.0;
+const WATER_SPEED_MULTIPLIER: f32 = 0.5;
+const SWIM_UP_SPEED: f32 = 2.5;
+const CRIM_SWIM_SPEED_MULTIPLIER: f32 = 0.4;
+const MAX_BREATH: f32 = 10.0; // Seconds underwater before drowning
+const BREATH_RECOVERY_RATE: f32 = 4.0;
+const DROWNING_DAMAGE: f32 = 10.0; // Health per second once out of breath
+const CRIM_MAX_HEALTH: f32 = 100.0;
+const FALLING_BLOCK_DAMAGE: f32 = 40.0;
+const ITEM_SIZE: f32 = 0.25;
+const ITEM_HOVER: f32 = 0.25; // Height the item floats at above where it rests
+const ITEM_BOB_HEIGHT: f32 = 0.08;
+const ITEM_BOB_SPEED: f32 = 2.5;
+const ITEM_SPIN_SPEED: f32 = 1.5;
+const ITEM_PICKUP_RADIUS: f32 = 1.5;
+const ITEM_MERGE_RADIUS: f32 = 0.75;
+const ITEM_LIFETIME: f32 = 300.0;
+const ITEM_GROUND_FRICTION: f32 = 8.0;
+const ITEM_POP_SPEED: f32 = 2.0; // Upward kick for blocks that break loose
+const ITEM_THROW_SPEED: f32 = 4.0;
+const ITEM_THROW_PICKUP_DELAY: f32 = 1.5;
+const MAX_LIGHT: u8 = 15;
+const LIGHT_FALLOFF: f32 = 0.8; // Brightness kept per light level below full
+const LIGHT_MARGIN: i32 = 2; // Lit cells around the edge of the world
+const LIGHT_BOTTOM: i32 = -1;
+const LIGHT_TOP: i32 = WORLD_HEIGHT * 3;
+const CRIM_SPAWN_MAX_LIGHT: u8 = 7;
+const LOG_END_SHADE: f32 = 0.6; // Brightness of a log's cut ends next to its bark
+const CRIM_DARK_VISION_SCALE: f32 = 0.5; // Vision range left against a target in pitch darkness
+const CRIM_LIGHT_AVERSION: u8 = 10; // Block light Crim refuses to walk into
+const CRIM_LIGHT_LOOKAHEAD: f32 = 0.6;
+const CRIM_OPEN_DOOR_TIME: f32 = 0.5; // Seconds Crim fumbles with a door before it opens
+const DOOR_NOISE_RADIUS: f32 = 6.0;
+const INTERACT_REACH: f32 = 5.0;
+const SHEEP_COUNT: usize = 8;
+const SHEEP_SPAWN_ATTEMPTS: usize = SHEEP_COUNT * 10; // Give up rather than loop forever on a flooded world
+const SHEEP_SPEED: f32 = 1.5;
+const SHEEP_FLEE_SPEED: f32 = 4.0;
+const SHEEP_FLEE_DISTANCE: f32 = 8.0;
+const SHEEP_HEALTH: u32 = 3;
+const SHEEP_HIT_REACH: f32 = 3.0;
+const SHELTER_MAX_VOLUME: usize = 512; // Flood fills larger than this count as open air
+
+const SUN_ILLUMINANCE: f32 = 10000.0;
+const DAY_AMBIENT_COLOR: [f32; 3] = [0.6, 0.6, 0.9];
+const NIGHT_AMBIENT_COLOR: [f32; 3] = [0.2, 0.2, 0.5];
+const DAY_AMBIENT_BRIGHTNESS: f32 = 0.3;
+const NIGHT_AMBIENT_BRIGHTNESS: f32 = 0.05;
+const DAY_SKY_COLOR: [f32; 3] = [0.53, 0.81, 0.92];
+const NIGHT_SKY_COLOR: [f32; 3] = [0.02, 0.02, 0.08];
+const CLOSE_CALL_DISTANCE: f32 = 3.0; // Crim got this close...
+const CLOSE_CALL_ESCAPE_DISTANCE: f32 = 8.0; // ...and the player still got away
+const BLOCK_MINED_SCORE: u32 = 5;
+const CLOSE_CALL_SCORE: u32 = 50;
+const WIN_BONUS_SCORE: u32 = 500;
+const MAX_HIGH_SCORES: usize = 10;
+const BEACON_REACH: f32 = 2.0;
+const CRIM_NIGHT_SPEED_MULTIPLIER: f32 = 1.3;
+const CRIM_NIGHT_VISION_MULTIPLIER: f32 = 1.5;
 
 // Components
 #[derive(Component)]
@@ -19,15 +123,112 @@ struct Player {
     velocity: Vec3,
     is_grounded: bool,
     selected_block_type: BlockType,
+    mining_target: Option<(i32, i32, i32)>,
+    mining_progress: f32,
 }
 
 #[derive(Component)]
 struct MainCamera;
 
+// The arm and tool only make sense in first person
+#[derive(Component)]
+struct PlayerArm;
+
+#[derive(Component)]
+struct Sun;
+
+// A gravity block on its way down, it turns back into a block where it lands
+#[derive(Component)]
+struct FallingBlock {
+    block_type: BlockType,
+    velocity: f32,
+}
+
+// A stack of blocks lying on the ground waiting to be picked up
+#[derive(Component)]
+struct DroppedItem {
+    block_type: BlockType,
+    count: u32,
+    position: Vec3, // Where it rests, the entity bobs around this
+    velocity: Vec3,
+    age: f32,
+    pickup_delay: f32, // Seconds before the player can pick it up
+}
+
+// A dropped item that came straight out of the ground, picking up ore like this counts towards the run
+#[derive(Component)]
+struct Mined;
+
 #[derive(Component)]
 struct Crim {
     chase_timer: f32,
     spotted_player: bool,
+    velocity: Vec3,
+    is_grounded: bool,
+    break_target: Option<(i32, i32, i32)>,
+    break_progress: f32,
+    speed: f32,
+    near_player: bool,
+    state: CrimState,
+    last_known_player_pos: Option<Vec3>,
+    heard_noise: Option<Vec3>,
+    target: Option<Vec3>,
+    health: f32,
+}
+
+// Direction Crim is looking in
+fn crim_facing(transform: &Transform) -> Vec3 {
+    transform.rotation * Vec3::Z
+}
+
+// Crim sees further and moves faster at night
+fn crim_vision_range(time_of_day: &TimeOfDay) -> f32 {
+    CRIM_VISION_RANGE * (1.0 + (CRIM_NIGHT_VISION_MULTIPLIER - 1.0) * time_of_day.night_factor())
+}
+
+fn crim_speed_multiplier(time_of_day: &TimeOfDay) -> f32 {
+    1.0 + (CRIM_NIGHT_SPEED_MULTIPLIER - 1.0) * time_of_day.night_factor()
+}
+
+// How far off Crim can make out something standing at `target`. Shadow under roofs and in caves
+// hides it, but the open sky still counts at night so the night bonus isn't undone.
+fn crim_sight_range(game_world: &GameWorld, target: Vec3, range: f32) -> f32 {
+    let light = game_world.light_level(block_coords(target)) as f32 / MAX_LIGHT as f32;
+    range * (CRIM_DARK_VISION_SCALE + (1.0 - CRIM_DARK_VISION_SCALE) * light)
+}
+
+// Whether Crim can see a point: in range, inside its vision cone and not behind blocks
+fn crim_can_see(game_world: &GameWorld, transform: &Transform, target: Vec3, range: f32) -> bool {
+    let to_target = target - transform.translation;
+    to_target.length() < crim_sight_range(game_world, target, range)
+        && crim_facing(transform).dot(to_target.normalize_or_zero()) > CRIM_VISION_COS
+        && raycast_blocks(game_world, transform.translation, target).is_none()
+}
+
+// Sent when the player does something loud enough for Crim to hear
+#[derive(Event)]
+struct NoiseEvent {
+    position: Vec3,
+    radius: f32,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum CrimState {
+    Idle,
+    Chasing,
+    Hunting,
+    Searching,
+    BreakingIn,
+}
+
+// A passive animal that wanders around and drops wool when hit
+#[derive(Component)]
+struct Sheep {
+    velocity: Vec3,
+    is_grounded: bool,
+    wander_direction: Vec3,
+    wander_timer: f32,
+    health: u32,
 }
 
 #[derive(Component)]
@@ -53,6 +254,342 @@ enum BlockType {
     Ore,
     Glass,
     Obsidian,
+    Wool,
+    Torch,
+    Lantern,
+    Door,
+    LockedDoor,
+    Trapdoor,
+    Chest,
+}
+
+impl BlockType {
+    const ALL: [BlockType; 16] = [
+        BlockType::Dirt,
+        BlockType::Stone,
+        BlockType::Wood,
+        BlockType::Grass,
+        BlockType::Sand,
+        BlockType::Water,
+        BlockType::Ore,
+        BlockType::Glass,
+        BlockType::Obsidian,
+        BlockType::Wool,
+        BlockType::Torch,
+        BlockType::Lantern,
+        BlockType::Door,
+        BlockType::LockedDoor,
+        BlockType::Trapdoor,
+        BlockType::Chest,
+    ];
+
+    // Parse the name used in save files and menus
+    fn from_name(name: &str) -> Option<BlockType> {
+        Self::ALL.into_iter().find(|block_type| format!("{:?}", block_type).eq_ignore_ascii_case(name))
+    }
+
+    fn material(&self) -> StandardMaterial {
+        match self {
+            BlockType::Dirt => StandardMaterial {
+                base_color: Color::srgb(0.6, 0.3, 0.1),
+                ..default()
+            },
+            BlockType::Stone => StandardMaterial {
+                base_color: Color::srgb(0.5, 0.5, 0.5),
+                ..default()
+            },
+            BlockType::Wood => StandardMaterial {
+                base_color: Color::srgb(0.6, 0.4, 0.2),
+                ..default()
+            },
+            BlockType::Grass => StandardMaterial {
+                base_color: Color::srgb(0.3, 0.7, 0.2),
+                ..default()
+            },
+            BlockType::Sand => StandardMaterial {
+                base_color: Color::srgb(0.9, 0.8, 0.5),
+                ..default()
+            },
+            BlockType::Water => StandardMaterial {
+                base_color: Color::srgb(0.2, 0.4, 0.8),
+                alpha_mode: AlphaMode::Blend,
+                metallic: 0.0,
+                perceptual_roughness: 0.1,
+                reflectance: 0.5,
+                ..default()
+            },
+            BlockType::Ore => StandardMaterial {
+                base_color: Color::srgb(0.4, 0.2, 0.6),
+                metallic: 0.7,
+                perceptual_roughness: 0.1,
+                ..default()
+            },
+            BlockType::Glass => StandardMaterial {
+                base_color: Color::srgb(0.8, 0.9, 1.0),
+                alpha_mode: AlphaMode::Blend,
+                metallic: 0.0,
+                perceptual_roughness: 0.0,
+                reflectance: 0.5,
+                ..default()
+            },
+            BlockType::Obsidian => StandardMaterial {
+                base_color: Color::srgb(0.1, 0.1, 0.2),
+                metallic: 0.2,
+                perceptual_roughness: 0.1,
+                ..default()
+            },
+            BlockType::Wool => StandardMaterial {
+                base_color: Color::srgb(0.95, 0.95, 0.9),
+                perceptual_roughness: 1.0,
+                ..default()
+            },
+            BlockType::Torch => StandardMaterial {
+                base_color: Color::srgb(1.0, 0.7, 0.3),
+                emissive: LinearRgba::rgb(4.0, 2.0, 0.5),
+                ..default()
+            },
+            BlockType::Lantern => StandardMaterial {
+                base_color: Color::srgb(1.0, 0.9, 0.6),
+                emissive: LinearRgba::rgb(5.0, 4.0, 2.0),
+                ..default()
+            },
+            BlockType::Door | BlockType::Trapdoor => StandardMaterial {
+                base_color: Color::srgb(0.45, 0.3, 0.15),
+                perceptual_roughness: 0.8,
+                ..default()
+            },
+            BlockType::Chest => StandardMaterial {
+                base_color: Color::srgb(0.55, 0.35, 0.15),
+                perceptual_roughness: 0.7,
+                ..default()
+            },
+            BlockType::LockedDoor => StandardMaterial {
+                base_color: Color::srgb(0.35, 0.3, 0.4),
+                metallic: 0.8,
+                perceptual_roughness: 0.3,
+                ..default()
+            },
+        }
+    }
+
+    // Blocks that open and close when used
+    fn is_openable(&self) -> bool {
+        matches!(self, BlockType::Door | BlockType::LockedDoor | BlockType::Trapdoor)
+    }
+
+    // Doors are two blocks tall and both halves move together
+    fn is_door(&self) -> bool {
+        matches!(self, BlockType::Door | BlockType::LockedDoor)
+    }
+
+    // Locked doors only open for the player
+    fn crim_can_open(&self) -> bool {
+        matches!(self, BlockType::Door | BlockType::Trapdoor)
+    }
+
+    // Blocks with an inventory of their own
+    fn is_container(&self) -> bool {
+        *self == BlockType::Chest
+    }
+
+    // Blocks that remember which way the player was looking when they were placed
+    fn has_facing(&self) -> bool {
+        self.is_openable()
+    }
+
+    // Logs lie along the axis of the face they were placed against
+    fn has_axis(&self) -> bool {
+        *self == BlockType::Wood
+    }
+
+    // Size of blocks that don't fill their cell, they stand on the bottom of it
+    fn shape(&self) -> Option<Vec3> {
+        match self {
+            BlockType::Torch => Some(Vec3::new(0.2, 0.6, 0.2)),
+            BlockType::Lantern => Some(Vec3::new(0.5, 0.5, 0.5)),
+            _ => None,
+        }
+    }
+
+    fn is_fluid(&self) -> bool {
+        *self == BlockType::Water
+    }
+
+    // Extra light lost passing through, None for blocks light can't get through
+    fn light_attenuation(&self) -> Option<u8> {
+        match self {
+            BlockType::Glass | BlockType::Torch | BlockType::Lantern => Some(0),
+            BlockType::Water => Some(2),
+            _ => None,
+        }
+    }
+
+    // Block light given off, 0 for blocks that don't glow
+    fn light_emission(&self) -> u8 {
+        match self {
+            BlockType::Torch => 13,
+            BlockType::Lantern => MAX_LIGHT,
+            _ => 0,
+        }
+    }
+
+    // Blocks that fall when nothing holds them up
+    fn has_gravity(&self) -> bool {
+        *self == BlockType::Sand
+    }
+
+    // Seconds Crim needs to smash through a block, on top of CRIM_BREAK_DELAY
+    fn hardness(&self) -> f32 {
+        match self {
+            BlockType::Water | BlockType::Torch => 0.5,
+            BlockType::Sand => 0.75,
+            BlockType::Dirt | BlockType::Grass | BlockType::Wool => 1.0,
+            BlockType::Glass | BlockType::Lantern => 1.5,
+            BlockType::Wood | BlockType::Door | BlockType::Chest => 3.0,
+            BlockType::Trapdoor => 2.0,
+            BlockType::LockedDoor => 8.0,
+            BlockType::Stone => 5.0,
+            BlockType::Ore => 6.0,
+            BlockType::Obsidian => 30.0,
+        }
+    }
+}
+
+// Horizontal direction a block faces, stored in two bits of its state
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Facing {
+    North,
+    East,
+    South,
+    West,
+}
+
+impl Facing {
+    const ALL: [Facing; 4] = [Facing::North, Facing::East, Facing::South, Facing::West];
+
+    // The facing closest to a direction, ignoring up and down
+    fn from_direction(direction: Vec3) -> Facing {
+        if direction.x.abs() > direction.z.abs() {
+            if direction.x > 0.0 { Facing::East } else { Facing::West }
+        } else if direction.z > 0.0 {
+            Facing::South
+        } else {
+            Facing::North
+        }
+    }
+
+    // Turns something built facing north (-Z) to face this way
+    fn rotation(&self) -> Quat {
+        let quarter_turns = match self {
+            Facing::North => 0.0,
+            Facing::West => 1.0,
+            Facing::South => 2.0,
+            Facing::East => 3.0,
+        };
+        Quat::from_rotation_y(quarter_turns * std::f32::consts::FRAC_PI_2)
+    }
+}
+
+// Axis a log runs along, upright by default
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Axis {
+    Y,
+    X,
+    Z,
+}
+
+impl Axis {
+    const ALL: [Axis; 3] = [Axis::Y, Axis::X, Axis::Z];
+
+    // Axis joining two neighbouring cells
+    fn between(a: (i32, i32, i32), b: (i32, i32, i32)) -> Axis {
+        if a.0 != b.0 {
+            Axis::X
+        } else if a.2 != b.2 {
+            Axis::Z
+        } else {
+            Axis::Y
+        }
+    }
+
+    // Turns something upright to run along this axis
+    fn rotation(&self) -> Quat {
+        match self {
+            Axis::Y => Quat::IDENTITY,
+            Axis::X => Quat::from_rotation_z(std::f32::consts::FRAC_PI_2),
+            Axis::Z => Quat::from_rotation_x(std::f32::consts::FRAC_PI_2),
+        }
+    }
+}
+
+// Property bits packed into a block state
+const LEVEL_BITS: u16 = 0b0_0000_0111;
+const FACING_BITS: u16 = 0b0_0001_1000;
+const FACING_SHIFT: u32 = 3;
+const OPEN_BIT: u16 = 0b0_0010_0000;
+const AXIS_BITS: u16 = 0b0_1100_0000;
+const AXIS_SHIFT: u32 = 6;
+const UPPER_BIT: u16 = 0b1_0000_0000;
+
+// A block as the world stores it: its type plus its property bits.
+// Bits 0-2 are the fluid level, 3-4 the facing, 5 whether it is open, 6-7 the axis and 8 the top half of a door.
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+struct BlockState {
+    block_type: BlockType,
+    properties: u16,
+}
+
+impl From<BlockType> for BlockState {
+    fn from(block_type: BlockType) -> Self {
+        Self { block_type, properties: 0 }
+    }
+}
+
+impl BlockState {
+    // 0 for a source, higher levels are thinner flowing water
+    fn level(&self) -> u8 {
+        (self.properties & LEVEL_BITS) as u8
+    }
+
+    fn with_level(self, level: u8) -> Self {
+        Self { properties: (self.properties & !LEVEL_BITS) | (level as u16 & LEVEL_BITS), ..self }
+    }
+
+    fn facing(&self) -> Facing {
+        Facing::ALL[((self.properties & FACING_BITS) >> FACING_SHIFT) as usize]
+    }
+
+    fn with_facing(self, facing: Facing) -> Self {
+        Self { properties: (self.properties & !FACING_BITS) | ((facing as u16) << FACING_SHIFT), ..self }
+    }
+
+    fn is_open(&self) -> bool {
+        self.properties & OPEN_BIT != 0
+    }
+
+    fn with_open(self, open: bool) -> Self {
+        let properties = if open { self.properties | OPEN_BIT } else { self.properties & !OPEN_BIT };
+        Self { properties, ..self }
+    }
+
+    fn axis(&self) -> Axis {
+        // The fourth value is unused, treat it as upright
+        Axis::ALL.get(((self.properties & AXIS_BITS) >> AXIS_SHIFT) as usize).copied().unwrap_or(Axis::Y)
+    }
+
+    fn with_axis(self, axis: Axis) -> Self {
+        Self { properties: (self.properties & !AXIS_BITS) | ((axis as u16) << AXIS_SHIFT), ..self }
+    }
+
+    // Which half of a door this is
+    fn is_upper(&self) -> bool {
+        self.properties & UPPER_BIT != 0
+    }
+
+    fn with_upper(self, upper: bool) -> Self {
+        let properties = if upper { self.properties | UPPER_BIT } else { self.properties & !UPPER_BIT };
+        Self { properties, ..self }
+    }
 }
 
 #[derive(Component, Clone)]
@@ -62,937 +599,5084 @@ struct Position {
     z: i32,
 }
 
-#[derive(Resource)]
-struct GameWorld {
-    blocks: HashMap<(i32, i32, i32), BlockType>,
+// Block entity data for a chest: what's stored inside it
+#[derive(Default, Clone)]
+struct Container {
+    items: HashMap<BlockType, u32>,
 }
 
-// Resource for tracking player stats
-#[derive(Resource)]
-struct PlayerStats {
-    pub health: f32,
-    pub max_health: f32,
-    pub inventory: HashMap<BlockType, u32>,
+// Move up to `count` of a block type between two inventories
+fn move_items(from: &mut HashMap<BlockType, u32>, to: &mut HashMap<BlockType, u32>, block_type: BlockType, count: u32) {
+    let Some(available) = from.get_mut(&block_type) else {
+        return;
+    };
+    let moved = count.min(*available);
+    *available -= moved;
+    if *available == 0 {
+        from.remove(&block_type);
+    }
+    *to.entry(block_type).or_insert(0) += moved;
 }
 
-// Resource for game UI
-#[derive(Resource)]
-struct GameUI {
-    show_debug: bool,
-    show_crosshair: bool,
+#[derive(Resource, Default)]
+struct GameWorld {
+    blocks: HashMap<(i32, i32, i32), BlockState>,
+    seed: u64, // Terrain generation seed
+    changed: Vec<(i32, i32, i32)>, // Cells edited since the block ticks last looked
+    sky_light: HashMap<(i32, i32, i32), u8>,
+    block_light: HashMap<(i32, i32, i32), u8>,
+    light_changed: HashSet<(i32, i32, i32)>, // Cells whose light the renderer hasn't caught up with
+    redraw: HashSet<(i32, i32, i32)>, // Cells whose block entity needs replacing after a state change
+    containers: HashMap<(i32, i32, i32), Container>, // Chest contents, a chest with nothing in it may have no entry
 }
 
-// Resource for game settings
-#[derive(Resource)]
-struct GameSettings {
-    pub render_distance: i32,
-    pub gravity_enabled: bool,
-}
+impl GameWorld {
+    fn block_type(&self, pos: (i32, i32, i32)) -> Option<BlockType> {
+        self.blocks.get(&pos).map(|state| state.block_type)
+    }
 
-// Systems
-fn setup(
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut game_world: ResMut<GameWorld>,
-    mut player_stats: ResMut<PlayerStats>,
-    game_settings: Res<GameSettings>,
-) {
-    // Add starting materials to player inventory
-    player_stats.inventory.insert(BlockType::Dirt, 20);
-    player_stats.inventory.insert(BlockType::Stone, 10);
-    
-    // Log the render distance
-    println!("Initializing world with render distance: {}", game_settings.render_distance);
-    // First-person camera will be attached to the player in the camera_follow system
+    // Fluids fill a cell without blocking it, and so do open doors and blocks smaller than the cell
+    fn is_solid(&self, pos: (i32, i32, i32)) -> bool {
+        self.blocks.get(&pos).is_some_and(|state| {
+            !state.block_type.is_fluid() && state.block_type.shape().is_none() && !state.is_open()
+        })
+    }
 
-    // Spawn light
-    commands.spawn((
-        DirectionalLight {
-            illuminance: 10000.0,
-            ..default()
-        },
-        Transform::from_xyz(10.0, 10.0, 10.0).looking_at(Vec3::ZERO, Vec3::Y),
-    ));
+    fn is_fluid(&self, pos: (i32, i32, i32)) -> bool {
+        self.block_type(pos).is_some_and(|block_type| block_type.is_fluid())
+    }
 
-    // Generate world
-    generate_world(&mut commands, &mut meshes, &mut materials, &mut game_world);
+    // Something holds up a block at `pos`, the bottom layer rests on the floor of the world
+    fn is_supported(&self, pos: (i32, i32, i32)) -> bool {
+        let (x, y, z) = pos;
+        y <= 0 || self.is_solid((x, y - 1, z))
+    }
 
-    // Spawn player with pickaxe
-    commands.spawn((
-        Mesh3d(meshes.add(Capsule3d::default())),
-        MeshMaterial3d(materials.add(Color::srgb(0.0, 0.0, 1.0))),
-        Transform::from_xyz(0.0, WORLD_HEIGHT as f32 + 1.0, 0.0),
-        Player {
-            has_pickaxe: true,
-            velocity: Vec3::ZERO,
-            is_grounded: false,
-            selected_block_type: BlockType::Dirt,
-        },
-    ))
-    .with_children(|parent| {
-        // Attach first-person camera to player
-        parent.spawn((
-            Camera3d::default(),
-            MainCamera,
-            Transform::from_xyz(0.0, 0.7, 0.0), // Position camera slightly above player center for eyes
-        ));
-        
-        // Add player arms/tool model visible in first person
-        parent.spawn((
-            Mesh3d(meshes.add(Cuboid::new(0.1, 0.1, 0.5))),
-            MeshMaterial3d(materials.add(Color::srgb(0.8, 0.6, 0.4))),
-            Transform::from_xyz(0.3, -0.2, -0.4)
-                .with_rotation(Quat::from_rotation_x(-0.3)),
-        ));
-    });
+    // Any part of a body standing at `pos` in water
+    fn body_in_fluid(&self, pos: Vec3) -> bool {
+        let (x, y, z) = block_coords(pos);
+        (0..BODY_HEIGHT).any(|dy| self.is_fluid((x, y + dy, z)))
+    }
 
-    // Spawn Crim (the monster)
-    commands.spawn((
-        Mesh3d(meshes.add(Capsule3d::default())),
-        MeshMaterial3d(materials.add(StandardMaterial {
-            base_color: Color::srgb(1.0, 0.0, 0.0),
-            emissive: Color::srgb(0.8, 0.0, 0.0).into(),
-            ..default()
-        })),
-        Transform::from_xyz(10.0, WORLD_HEIGHT as f32 + 1.0, 10.0),
-        Crim {
-            chase_timer: 0.0,
-            spotted_player: false,
-        },
-    ))
-    .with_children(|parent| {
-        // Add glowing eyes
-        parent.spawn((
-            Mesh3d(meshes.add(Sphere::new(0.1))),
-            MeshMaterial3d(materials.add(StandardMaterial {
-                base_color: Color::srgb(1.0, 1.0, 0.3),
-                emissive: Color::srgb(1.0, 1.0, 0.3).into(),
-                ..default()
-            })),
-            Transform::from_xyz(0.2, 0.3, -0.3),
-        ));
-        
-        parent.spawn((
-            Mesh3d(meshes.add(Sphere::new(0.1))),
-            MeshMaterial3d(materials.add(StandardMaterial {
-                base_color: Color::srgb(1.0, 1.0, 0.3),
-                emissive: Color::srgb(1.0, 1.0, 0.3).into(),
-                ..default()
-            })),
-            Transform::from_xyz(-0.2, 0.3, -0.3),
-        ));
-    });
-}
+    // The whole body is under, no air to breathe
+    fn head_in_fluid(&self, pos: Vec3) -> bool {
+        let (x, y, z) = block_coords(pos);
+        self.is_fluid((x, y + BODY_HEIGHT - 1, z))
+    }
 
-fn generate_world(
-    commands: &mut Commands,
-    meshes: &mut ResMut<Assets<Mesh>>,
-    materials: &mut ResMut<Assets<StandardMaterial>>,
-    game_world: &mut ResMut<GameWorld>,
-) {
-    let mut rng = rand::rng();
-    let cube_mesh = meshes.add(Cuboid::default());
-    
-    // Create materials for all block types
-    let dirt_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.6, 0.3, 0.1),
-        ..default()
-    });
-    let stone_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.5, 0.5, 0.5),
-        ..default()
-    });
-    let wood_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.6, 0.4, 0.2),
-        ..default()
-    });
-    let grass_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.3, 0.7, 0.2),
-        ..default()
-    });
-    let sand_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.9, 0.8, 0.5),
-        ..default()
-    });
-    let water_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.2, 0.4, 0.8),
-        alpha_mode: AlphaMode::Blend,
-        metallic: 0.0,
-        perceptual_roughness: 0.1,
-        reflectance: 0.5,
-        ..default()
-    });
-    let ore_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.4, 0.2, 0.6),
-        metallic: 0.7,
-        perceptual_roughness: 0.1,
-        ..default()
-    });
-    let glass_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.8, 0.9, 1.0),
-        alpha_mode: AlphaMode::Blend,
-        metallic: 0.0,
-        perceptual_roughness: 0.0,
-        reflectance: 0.5,
-        ..default()
-    });
-    let obsidian_material = materials.add(StandardMaterial {
-        base_color: Color::srgb(0.1, 0.1, 0.2),
-        metallic: 0.2,
-        perceptual_roughness: 0.1,
-        ..default()
-    });
+    // Put a block in the world and let the simulation know the cell changed
+    fn place_block(&mut self, pos: (i32, i32, i32), state: impl Into<BlockState>) {
+        self.blocks.insert(pos, state.into());
+        self.changed.push(pos);
+        self.update_light(pos);
+    }
 
-    // Generate terrain
-    for x in -WORLD_SIZE..WORLD_SIZE {
-        for z in -WORLD_SIZE..WORLD_SIZE {
-            let height = (rng.random::<f32>() * 3.0).floor() as i32;
-            
-            // Create ground layer with more varied terrain
-            for y in 0..=height {
-                // More varied terrain generation
-                let block_type = if y == height && height > 0 {
-                    if rng.random_bool(0.6) { BlockType::Grass } else { BlockType::Dirt }
-                } else if y == height && height == 0 {
-                    if rng.random_bool(0.7) { BlockType::Sand } else { BlockType::Dirt }
-                } else if y == 0 {
-                    if rng.random_bool(0.05) { BlockType::Obsidian } else { BlockType::Stone }
-                } else if y < height - 2 && rng.random_bool(0.05) {
-                    BlockType::Ore
-                } else if rng.random_bool(0.8) {
-                    BlockType::Stone
-                } else {
-                    BlockType::Dirt
+    // Anything still in a container goes with it, take the contents out first to keep them
+    fn remove_block(&mut self, pos: (i32, i32, i32)) -> Option<BlockType> {
+        self.containers.remove(&pos);
+        let removed = self.blocks.remove(&pos);
+        if removed.is_some() {
+            self.changed.push(pos);
+            self.update_light(pos);
+        }
+        removed.map(|state| state.block_type)
+    }
+
+    // Cells making up the door or trapdoor at `pos`
+    fn door_cells(&self, pos: (i32, i32, i32)) -> Vec<(i32, i32, i32)> {
+        let Some(state) = self.blocks.get(&pos).filter(|state| state.block_type.is_openable()) else {
+            return Vec::new();
+        };
+
+        // The other half is the opposite half of the same door, not a neighbouring door stacked on this one
+        let (x, y, z) = pos;
+        let mut cells = vec![pos];
+        if state.block_type.is_door() {
+            let other = if state.is_upper() { (x, y - 1, z) } else { (x, y + 1, z) };
+            if self.blocks.get(&other).is_some_and(|other| other.block_type == state.block_type && other.is_upper() != state.is_upper()) {
+                cells.push(other);
+            }
+        }
+        cells
+    }
+
+    // Swing a door or trapdoor open or shut
+    fn set_open(&mut self, pos: (i32, i32, i32), open: bool) {
+        for cell in self.door_cells(pos) {
+            if let Some(state) = self.blocks.get_mut(&cell) {
+                *state = state.with_open(open);
+            }
+            self.changed.push(cell);
+            self.redraw.insert(cell);
+            self.update_light(cell);
+        }
+    }
+
+    // 0 for a source, higher levels are thinner flowing water
+    fn water_level(&self, pos: (i32, i32, i32)) -> Option<u8> {
+        self.blocks.get(&pos)
+            .filter(|state| state.block_type == BlockType::Water)
+            .map(|state| state.level())
+    }
+
+    // First solid cell a body standing at `pos` would overlap, feet first
+    fn body_collision(&self, pos: Vec3) -> Option<(i32, i32, i32)> {
+        let (x, y, z) = block_coords(pos);
+        (0..BODY_HEIGHT)
+            .map(|dy| (x, y + dy, z))
+            .find(|cell| self.is_solid(*cell))
+    }
+
+    // Dark enough for Crim to spawn, sky light only counts as much as the sun is up
+    fn is_dark(&self, pos: (i32, i32, i32), daylight: f32) -> bool {
+        self.effective_light(pos, daylight) <= CRIM_SPAWN_MAX_LIGHT
+    }
+
+    fn light(&self, channel: LightChannel, pos: (i32, i32, i32)) -> u8 {
+        let levels = match channel {
+            LightChannel::Sky => &self.sky_light,
+            LightChannel::Block => &self.block_light,
+        };
+        match levels.get(&pos) {
+            Some(level) => *level,
+            // Open sky all around the lit part of the world
+            None if channel == LightChannel::Sky && !in_light_bounds(pos) => MAX_LIGHT,
+            None => 0,
+        }
+    }
+
+    fn set_light(&mut self, channel: LightChannel, pos: (i32, i32, i32), level: u8) {
+        let levels = match channel {
+            LightChannel::Sky => &mut self.sky_light,
+            LightChannel::Block => &mut self.block_light,
+        };
+        if level == 0 {
+            levels.remove(&pos);
+        } else {
+            levels.insert(pos, level);
+        }
+        self.light_changed.insert(pos);
+    }
+
+    // How much extra light a cell soaks up, None when it blocks light completely
+    fn light_attenuation(&self, pos: (i32, i32, i32)) -> Option<u8> {
+        match self.blocks.get(&pos) {
+            Some(state) if state.is_open() => Some(0),
+            Some(state) => state.block_type.light_attenuation(),
+            None => Some(0),
+        }
+    }
+
+    fn light_level(&self, pos: (i32, i32, i32)) -> u8 {
+        self.light(LightChannel::Sky, pos).max(self.light(LightChannel::Block, pos))
+    }
+
+    fn effective_light(&self, pos: (i32, i32, i32), daylight: f32) -> u8 {
+        let sky = (self.light(LightChannel::Sky, pos) as f32 * daylight).round() as u8;
+        sky.max(self.light(LightChannel::Block, pos))
+    }
+
+    // How lit a block looks, taken from the brightest cell touching it
+    fn block_brightness(&self, pos: (i32, i32, i32)) -> u8 {
+        let (x, y, z) = pos;
+        [pos, (x + 1, y, z), (x - 1, y, z), (x, y + 1, z), (x, y - 1, z), (x, y, z + 1), (x, y, z - 1)]
+            .into_iter()
+            .map(|cell| self.light_level(cell))
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Flood light outwards from the queued cells, one level fainter per step
+    fn propagate_light(&mut self, channel: LightChannel, mut queue: VecDeque<(i32, i32, i32)>) {
+        while let Some(pos) = queue.pop_front() {
+            let level = self.light(channel, pos);
+            if level == 0 {
+                continue;
+            }
+
+            for (dx, dy, dz) in LIGHT_DIRECTIONS {
+                let next = (pos.0 + dx, pos.1 + dy, pos.2 + dz);
+                if !in_light_bounds(next) {
+                    continue;
+                }
+                let Some(attenuation) = self.light_attenuation(next) else {
+                    continue;
                 };
-                
-                // Create water pools in low areas
-                let is_water_level = height < 1 && y == 1 && rng.random_bool(0.4);
-                let final_block_type = if is_water_level { BlockType::Water } else { block_type };
-                
-                game_world.blocks.insert((x, y, z), final_block_type);
-                
-                let material = match final_block_type {
-                    BlockType::Dirt => dirt_material.clone(),
-                    BlockType::Stone => stone_material.clone(),
-                    BlockType::Wood => wood_material.clone(),
-                    BlockType::Grass => grass_material.clone(),
-                    BlockType::Sand => sand_material.clone(),
-                    BlockType::Water => water_material.clone(),
-                    BlockType::Ore => ore_material.clone(),
-                    BlockType::Glass => glass_material.clone(),
-                    BlockType::Obsidian => obsidian_material.clone(),
+
+                // Full sunlight pours straight down through open air without fading
+                let next_level = if channel == LightChannel::Sky && dy == -1 && level == MAX_LIGHT && attenuation == 0 {
+                    MAX_LIGHT
+                } else {
+                    level.saturating_sub(1 + attenuation)
                 };
-                
-                commands.spawn((
-                    Mesh3d(cube_mesh.clone()),
-                    MeshMaterial3d(material),
-                    Transform::from_xyz(
-                        x as f32 * BLOCK_SIZE,
-                        y as f32 * BLOCK_SIZE,
-                        z as f32 * BLOCK_SIZE,
-                    ),
-                    Block { block_type },
-                    Position { x, y, z },
-                ));
+                if next_level > self.light(channel, next) {
+                    self.set_light(channel, next, next_level);
+                    queue.push_back(next);
+                }
             }
         }
     }
 
-    // Add some trees and structures
-    for _ in 0..20 {
-        let x = rng.random_range(-WORLD_SIZE+2..WORLD_SIZE-2);
-        let z = rng.random_range(-WORLD_SIZE+2..WORLD_SIZE-2);
-        
-        if let Some(base_height) = game_world.blocks.keys()
-            .filter(|(bx, _, bz)| *bx == x && *bz == z)
-            .map(|(_, by, _)| *by)
-            .max()
-        {
-            // Decide what to generate - trees or small structures
-            let structure_type = rng.random_range(0..10);
-            
-            match structure_type {
-                // Trees (70% chance)
-                0..=6 => {
-                    // Tree trunk
-                    for y in base_height + 1..base_height + 6 {
-                        game_world.blocks.insert((x, y, z), BlockType::Wood);
-                        
-                        commands.spawn((
-                            Mesh3d(cube_mesh.clone()),
-                            MeshMaterial3d(wood_material.clone()),
-                            Transform::from_xyz(
-                                x as f32 * BLOCK_SIZE,
-                                y as f32 * BLOCK_SIZE,
-                                z as f32 * BLOCK_SIZE,
-                            ),
-                            Block { block_type: BlockType::Wood },
-                            Position { x, y, z },
-                        ));
+    // Light the whole world from scratch, used after generating or loading
+    fn relight_all(&mut self) {
+        self.sky_light.clear();
+        self.block_light.clear();
+
+        let mut sky = VecDeque::new();
+        for x in -WORLD_SIZE - LIGHT_MARGIN..WORLD_SIZE + LIGHT_MARGIN {
+            for z in -WORLD_SIZE - LIGHT_MARGIN..WORLD_SIZE + LIGHT_MARGIN {
+                for y in (LIGHT_BOTTOM..=LIGHT_TOP).rev() {
+                    if self.light_attenuation((x, y, z)) != Some(0) {
+                        break;
                     }
-                    
-                    // Tree leaves
-                    let tree_top = base_height + 5;
-                    for leaf_x in x-2..=x+2 {
-                        for leaf_z in z-2..=z+2 {
-                            // Skip corners for a more natural shape
-                            if (leaf_x == x-2 || leaf_x == x+2) && (leaf_z == z-2 || leaf_z == z+2) {
-                                continue;
-                            }
-                            
-                            for leaf_y in tree_top-1..=tree_top+1 {
-                                // Skip if there's already a block here
-                                if game_world.blocks.contains_key(&(leaf_x, leaf_y, leaf_z)) {
-                                    continue;
-                                }
-                                
-                                // Add leaf block
-                                game_world.blocks.insert((leaf_x, leaf_y, leaf_z), BlockType::Grass);
-                                
-                                commands.spawn((
-                                    Mesh3d(cube_mesh.clone()),
-                                    MeshMaterial3d(grass_material.clone()),
-                                    Transform::from_xyz(
-                                        leaf_x as f32 * BLOCK_SIZE,
-                                        leaf_y as f32 * BLOCK_SIZE,
-                                        leaf_z as f32 * BLOCK_SIZE,
-                                    ),
-                                    Block { block_type: BlockType::Grass },
-                                    Position { x: leaf_x, y: leaf_y, z: leaf_z },
-                                ));
-                            }
-                        }
+                    self.set_light(LightChannel::Sky, (x, y, z), MAX_LIGHT);
+                    sky.push_back((x, y, z));
+                }
+            }
+        }
+        self.propagate_light(LightChannel::Sky, sky);
+
+        let emitters: Vec<_> = self.blocks.iter()
+            .filter(|(_, state)| state.block_type.light_emission() > 0)
+            .map(|(pos, state)| (*pos, state.block_type.light_emission()))
+            .collect();
+        let mut block = VecDeque::new();
+        for (pos, emission) in emitters {
+            self.set_light(LightChannel::Block, pos, emission);
+            block.push_back(pos);
+        }
+        self.propagate_light(LightChannel::Block, block);
+
+        // Every block entity is new at this point and picks up its light anyway
+        self.light_changed.clear();
+    }
+
+    // Relight around a cell whose block just changed
+    fn update_light(&mut self, pos: (i32, i32, i32)) {
+        if !in_light_bounds(pos) {
+            return;
+        }
+
+        for channel in [LightChannel::Sky, LightChannel::Block] {
+            // Take away all the light that could have passed through this cell
+            let mut darken = VecDeque::from([(pos, self.light(channel, pos))]);
+            let mut darkened = vec![pos];
+            let mut relight = VecDeque::new();
+            self.set_light(channel, pos, 0);
+
+            while let Some((cell, level)) = darken.pop_front() {
+                for (dx, dy, dz) in LIGHT_DIRECTIONS {
+                    let next = (cell.0 + dx, cell.1 + dy, cell.2 + dz);
+                    if !in_light_bounds(next) {
+                        continue;
                     }
-                },
-                
-                // Stone pillar (20% chance)
-                7..=8 => {
-                    let height = rng.random_range(4..8);
-                    for y in base_height + 1..base_height + height {
-                        game_world.blocks.insert((x, y, z), BlockType::Stone);
-                        
-                        commands.spawn((
-                            Mesh3d(cube_mesh.clone()),
-                            MeshMaterial3d(stone_material.clone()),
-                            Transform::from_xyz(
-                                x as f32 * BLOCK_SIZE,
-                                y as f32 * BLOCK_SIZE,
-                                z as f32 * BLOCK_SIZE,
-                            ),
-                            Block { block_type: BlockType::Stone },
-                            Position { x, y, z },
-                        ));
-                        
-                        // Add some obsidian at the top
-                        if y == base_height + height - 1 {
-                            game_world.blocks.insert((x, y+1, z), BlockType::Obsidian);
-                            
-                            commands.spawn((
-                                Mesh3d(cube_mesh.clone()),
-                                MeshMaterial3d(obsidian_material.clone()),
-                                Transform::from_xyz(
-                                    x as f32 * BLOCK_SIZE,
-                                    (y+1) as f32 * BLOCK_SIZE,
-                                    z as f32 * BLOCK_SIZE,
-                                ),
-                                Block { block_type: BlockType::Obsidian },
-                                Position { x, y: y+1, z },
-                            ));
-                        }
+                    let next_level = self.light(channel, next);
+                    if next_level == 0 {
+                        continue;
                     }
-                },
-                
-                // Glass tower (10% chance)
-                9 => {
-                    let height = rng.random_range(3..6);
-                    for y in base_height + 1..base_height + height {
-                        game_world.blocks.insert((x, y, z), BlockType::Glass);
-                        
-                        commands.spawn((
-                            Mesh3d(cube_mesh.clone()),
-                            MeshMaterial3d(glass_material.clone()),
-                            Transform::from_xyz(
-                                x as f32 * BLOCK_SIZE,
-                                y as f32 * BLOCK_SIZE,
-                                z as f32 * BLOCK_SIZE,
-                            ),
-                            Block { block_type: BlockType::Glass },
-                            Position { x, y, z },
-                        ));
+
+                    let fed_by_cell = next_level < level
+                        || (channel == LightChannel::Sky && dy == -1 && level == MAX_LIGHT && next_level == MAX_LIGHT);
+                    if fed_by_cell {
+                        self.set_light(channel, next, 0);
+                        darken.push_back((next, next_level));
+                        darkened.push(next);
+                    } else {
+                        relight.push_back(next);
                     }
-                },
-                
-                _ => {}
+                }
+            }
+
+            // Put back light sources caught in the dark area, then let the surroundings shine back in
+            for cell in darkened {
+                let source = match channel {
+                    LightChannel::Sky if cell.1 == LIGHT_TOP && self.light_attenuation(cell) == Some(0) => MAX_LIGHT,
+                    LightChannel::Sky => 0,
+                    LightChannel::Block => self.block_type(cell).map_or(0, |block_type| block_type.light_emission()),
+                };
+                if source > 0 {
+                    self.set_light(channel, cell, source);
+                    relight.push_back(cell);
+                }
+            }
+            self.propagate_light(channel, relight);
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum LightChannel {
+    Sky,
+    Block,
+}
+
+const LIGHT_DIRECTIONS: [(i32, i32, i32); 6] = [(1, 0, 0), (-1, 0, 0), (0, 1, 0), (0, -1, 0), (0, 0, 1), (0, 0, -1)];
+
+// Light is only tracked in and around the generated world, outside it is open sky
+fn in_light_bounds(pos: (i32, i32, i32)) -> bool {
+    let (x, y, z) = pos;
+    (-WORLD_SIZE - LIGHT_MARGIN..WORLD_SIZE + LIGHT_MARGIN).contains(&x)
+        && (-WORLD_SIZE - LIGHT_MARGIN..WORLD_SIZE + LIGHT_MARGIN).contains(&z)
+        && (LIGHT_BOTTOM..=LIGHT_TOP).contains(&y)
+}
+
+// Shared cube mesh and one material per block type
+#[derive(Resource)]
+struct BlockAssets {
+    cube_mesh: Handle<Mesh>,
+    lit_meshes: Vec<Handle<Mesh>>, // Cube with the brightness of each light level baked into its vertex colours
+    lit_log_meshes: Vec<Handle<Mesh>>, // Same again with darker end grain on the top and bottom faces
+    shaped_meshes: HashMap<BlockType, Handle<Mesh>>, // Blocks smaller than a full cube
+    materials: HashMap<BlockType, Handle<StandardMaterial>>,
+    flowing_water: Handle<StandardMaterial>,
+}
+
+fn spawn_block(
+    commands: &mut Commands,
+    block_assets: &BlockAssets,
+    pos: (i32, i32, i32),
+    state: impl Into<BlockState>,
+) -> Entity {
+    let state = state.into();
+    let block_type = state.block_type;
+    let (x, y, z) = pos;
+    let center = Vec3::new(x as f32, y as f32, z as f32) * BLOCK_SIZE;
+    let mesh = block_assets.shaped_meshes.get(&block_type).unwrap_or(&block_assets.cube_mesh);
+    let mut material = &block_assets.materials[&block_type];
+    let mut transform = Transform::from_translation(center);
+
+    if block_type.is_fluid() && state.level() > 0 {
+        // Flowing water is drawn lower and lighter the further it is from its source
+        let height = (8 - state.level()) as f32 / 8.0;
+        material = &block_assets.flowing_water;
+        transform = Transform::from_translation(center + Vec3::Y * (height - 1.0) / 2.0 * BLOCK_SIZE)
+            .with_scale(Vec3::new(1.0, height, 1.0));
+    } else if block_type.is_openable() {
+        transform = panel_transform(center, state);
+    } else if block_type.has_axis() {
+        transform.rotation = state.axis().rotation();
+    }
+
+    commands.spawn((
+        Mesh3d(mesh.clone()),
+        MeshMaterial3d(material.clone()),
+        transform,
+        Block { block_type },
+        Position { x, y, z },
+    )).id()
+}
+
+// Doors and trapdoors are thin panels turned the way they were placed, open ones swing back against the side
+fn panel_transform(center: Vec3, state: BlockState) -> Transform {
+    let (offset, scale) = match (state.block_type, state.is_open()) {
+        // A shut trapdoor lies flat across the top of its cell
+        (BlockType::Trapdoor, false) => (Vec3::new(0.0, 0.4, 0.0), Vec3::new(1.0, 0.2, 1.0)),
+        (_, false) => (Vec3::new(0.0, 0.0, -0.4), Vec3::new(1.0, 1.0, 0.2)),
+        (_, true) => (Vec3::new(-0.4, 0.0, 0.0), Vec3::new(0.2, 1.0, 1.0)),
+    };
+    let rotation = state.facing().rotation();
+    Transform::from_translation(center + rotation * offset * BLOCK_SIZE)
+        .with_rotation(rotation)
+        .with_scale(scale)
+}
+
+fn spawn_dropped_item(
+    commands: &mut Commands,
+    block_assets: &BlockAssets,
+    position: Vec3,
+    block_type: BlockType,
+    count: u32,
+    velocity: Vec3,
+    pickup_delay: f32,
+) -> Entity {
+    commands.spawn((
+        Mesh3d(block_assets.cube_mesh.clone()),
+        MeshMaterial3d(block_assets.materials[&block_type].clone()),
+        Transform::from_translation(position).with_scale(Vec3::splat(ITEM_SIZE)),
+        DroppedItem {
+            block_type,
+            count,
+            position,
+            velocity,
+            age: 0.0,
+            pickup_delay,
+        },
+    )).id()
+}
+
+// Pop a broken block loose from its cell with a little random sideways kick
+fn spawn_block_drop(
+    commands: &mut Commands,
+    block_assets: &BlockAssets,
+    cell: (i32, i32, i32),
+    block_type: BlockType,
+    count: u32,
+) -> Entity {
+    let mut rng = rand::rng();
+    let velocity = Vec3::new(rng.random_range(-1.0..1.0), ITEM_POP_SPEED, rng.random_range(-1.0..1.0));
+    spawn_dropped_item(commands, block_assets, cell_center(cell), block_type, count, velocity, 0.0)
+}
+
+// Empty a chest that's about to break onto the ground
+fn spill_container(
+    commands: &mut Commands,
+    block_assets: &BlockAssets,
+    game_world: &mut GameWorld,
+    cell: (i32, i32, i32),
+) {
+    if let Some(container) = game_world.containers.remove(&cell) {
+        for (block_type, count) in container.items {
+            spawn_block_drop(commands, block_assets, cell, block_type, count);
+        }
+    }
+}
+
+// Grid cell containing a world-space position
+fn block_coords(pos: Vec3) -> (i32, i32, i32) {
+    (
+        (pos.x / BLOCK_SIZE).floor() as i32,
+        (pos.y / BLOCK_SIZE).floor() as i32,
+        (pos.z / BLOCK_SIZE).floor() as i32,
+    )
+}
+
+// Apply gravity to a body and return whether it is standing on a block
+fn apply_gravity(game_world: &GameWorld, translation: &mut Vec3, velocity: &mut Vec3, delta: f32) -> bool {
+    if game_world.body_in_fluid(*translation) {
+        // Water holds bodies up and slows their fall
+        let buoyancy = if game_world.head_in_fluid(*translation) { WATER_BUOYANCY } else { 0.0 };
+        velocity.y += (GRAVITY * WATER_GRAVITY_SCALE + buoyancy) * delta;
+        velocity.y = velocity.y.max(-WATER_MAX_SINK_SPEED);
+    } else {
+        velocity.y += GRAVITY * delta;
+    }
+
+    let potential_position = *translation + *velocity * delta;
+    let (x, y, z) = block_coords(potential_position);
+
+    // Stop falling when we would end up inside a block
+    if game_world.is_solid((x, y, z)) {
+        velocity.y = 0.0;
+    } else {
+        *translation = potential_position;
+    }
+
+    let grounded = game_world.is_solid((x, y - 1, z));
+    if grounded && velocity.y <= 0.0 {
+        velocity.y = 0.0;
+    }
+    grounded
+}
+
+// World-space center of a grid cell
+fn cell_center(cell: (i32, i32, i32)) -> Vec3 {
+    (Vec3::new(cell.0 as f32, cell.1 as f32, cell.2 as f32) + Vec3::splat(0.5)) * BLOCK_SIZE
+}
+
+// First solid cell between two points, or None if the line is clear
+fn raycast_blocks(game_world: &GameWorld, from: Vec3, to: Vec3) -> Option<(i32, i32, i32)> {
+    let ray_direction = (to - from).normalize_or_zero();
+    let ray_length = (to - from).length();
+    let ray_steps = (ray_length / 0.5).ceil() as i32; // Check every 0.5 units
+
+    (1..ray_steps)
+        .map(|i| block_coords(from + ray_direction * (i as f32 * 0.5)))
+        .find(|cell| game_world.is_solid(*cell))
+}
+
+// Move a body horizontally one axis at a time, stepping up single-block ledges.
+// Returns the cell that stopped the movement, if any.
+fn walk_body(
+    game_world: &GameWorld,
+    translation: &mut Vec3,
+    movement: Vec3,
+    is_grounded: bool,
+) -> Option<(i32, i32, i32)> {
+    let mut blocked_by = None;
+
+    for step in [Vec3::new(movement.x, 0.0, 0.0), Vec3::new(0.0, 0.0, movement.z)] {
+        if step == Vec3::ZERO {
+            continue;
+        }
+
+        let target = *translation + step;
+        match game_world.body_collision(target) {
+            None => *translation = target,
+            Some(cell) => {
+                let step_up = target + Vec3::Y * BLOCK_SIZE;
+                if is_grounded && game_world.body_collision(step_up).is_none() {
+                    *translation = step_up;
+                } else if blocked_by.is_none() {
+                    blocked_by = Some(cell);
+                }
             }
         }
     }
+
+    blocked_by
+}
+
+// Everything the player can do, gameplay systems read these instead of raw keys
+#[derive(PartialEq, Eq, Hash, Clone, Copy, Debug)]
+enum InputAction {
+    MoveForward,
+    MoveBack,
+    MoveLeft,
+    MoveRight,
+    Jump,
+    Descend,
+    Mine,
+    Place,
+    SelectSlot(u8), // 1 to 10, slot 10 sits on the 0 key
+    NextSlot,
+    PreviousSlot,
+    ToggleAiDebug,
+    CycleCamera,
+    OpenConsole,
+    OpenCrafting,
+    Interact,
+    Drop,
+    Pause,
+}
+
+impl InputAction {
+    const ALL: [InputAction; 27] = [
+        InputAction::MoveForward,
+        InputAction::MoveBack,
+        InputAction::MoveLeft,
+        InputAction::MoveRight,
+        InputAction::Jump,
+        InputAction::Descend,
+        InputAction::Mine,
+        InputAction::Place,
+        InputAction::SelectSlot(1),
+        InputAction::SelectSlot(2),
+        InputAction::SelectSlot(3),
+        InputAction::SelectSlot(4),
+        InputAction::SelectSlot(5),
+        InputAction::SelectSlot(6),
+        InputAction::SelectSlot(7),
+        InputAction::SelectSlot(8),
+        InputAction::SelectSlot(9),
+        InputAction::SelectSlot(10),
+        InputAction::NextSlot,
+        InputAction::PreviousSlot,
+        InputAction::ToggleAiDebug,
+        InputAction::CycleCamera,
+        InputAction::OpenConsole,
+        InputAction::OpenCrafting,
+        InputAction::Interact,
+        InputAction::Drop,
+        InputAction::Pause,
+    ];
+
+    fn label(&self) -> String {
+        match self {
+            InputAction::MoveForward => "Move forward".to_string(),
+            InputAction::MoveBack => "Move back".to_string(),
+            InputAction::MoveLeft => "Move left".to_string(),
+            InputAction::MoveRight => "Move right".to_string(),
+            InputAction::Jump => "Jump / up".to_string(),
+            InputAction::Descend => "Down".to_string(),
+            InputAction::Mine => "Mine".to_string(),
+            InputAction::Place => "Place".to_string(),
+            InputAction::SelectSlot(slot) => format!("Slot {}", slot),
+            InputAction::NextSlot => "Next slot".to_string(),
+            InputAction::PreviousSlot => "Previous slot".to_string(),
+            InputAction::ToggleAiDebug => "AI overlay".to_string(),
+            InputAction::CycleCamera => "Camera mode".to_string(),
+            InputAction::OpenConsole => "Console".to_string(),
+            InputAction::OpenCrafting => "Crafting".to_string(),
+            InputAction::Interact => "Use door".to_string(),
+            InputAction::Drop => "Drop item".to_string(),
+            InputAction::Pause => "Pause".to_string(),
+        }
+    }
+}
+
+// Block for each hotbar slot, SelectSlot(n) picks HOTBAR[n - 1] and the slots past 10 are reached by cycling
+const HOTBAR: [BlockType; 16] = [
+    BlockType::Dirt,
+    BlockType::Stone,
+    BlockType::Wood,
+    BlockType::Grass,
+    BlockType::Sand,
+    BlockType::Glass,
+    BlockType::Obsidian,
+    BlockType::Ore,
+    BlockType::Water,
+    BlockType::Wool,
+    BlockType::Torch,
+    BlockType::Lantern,
+    BlockType::Door,
+    BlockType::LockedDoor,
+    BlockType::Trapdoor,
+    BlockType::Chest,
+];
+
+struct Recipe {
+    output: BlockType,
+    count: u32,
+    ingredients: &'static [(BlockType, u32)],
+}
+
+const RECIPES: [Recipe; 6] = [
+    Recipe {
+        output: BlockType::Torch,
+        count: 4,
+        ingredients: &[(BlockType::Wood, 1), (BlockType::Ore, 1)],
+    },
+    Recipe {
+        output: BlockType::Lantern,
+        count: 1,
+        ingredients: &[(BlockType::Wood, 2), (BlockType::Ore, 2)],
+    },
+    Recipe {
+        output: BlockType::Door,
+        count: 1,
+        ingredients: &[(BlockType::Wood, 2)],
+    },
+    Recipe {
+        output: BlockType::LockedDoor,
+        count: 1,
+        ingredients: &[(BlockType::Wood, 1), (BlockType::Ore, 3)],
+    },
+    Recipe {
+        output: BlockType::Trapdoor,
+        count: 2,
+        ingredients: &[(BlockType::Wood, 1)],
+    },
+    Recipe {
+        output: BlockType::Chest,
+        count: 1,
+        ingredients: &[(BlockType::Wood, 4)],
+    },
+];
+
+impl Recipe {
+    fn can_craft(&self, inventory: &HashMap<BlockType, u32>) -> bool {
+        self.ingredients.iter().all(|(block_type, needed)| inventory.get(block_type).copied().unwrap_or(0) >= *needed)
+    }
+
+    fn craft(&self, inventory: &mut HashMap<BlockType, u32>) {
+        if !self.can_craft(inventory) {
+            return;
+        }
+        for (block_type, needed) in self.ingredients {
+            if let Some(count) = inventory.get_mut(block_type) {
+                *count -= needed;
+                if *count == 0 {
+                    inventory.remove(block_type);
+                }
+            }
+        }
+        *inventory.entry(self.output).or_insert(0) += self.count;
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+}
+
+// Keys that can be bound, anything else is ignored while rebinding
+const BINDABLE_KEYS: [KeyCode; 64] = [
+    KeyCode::KeyA, KeyCode::KeyB, KeyCode::KeyC, KeyCode::KeyD, KeyCode::KeyE, KeyCode::KeyF,
+    KeyCode::KeyG, KeyCode::KeyH, KeyCode::KeyI, KeyCode::KeyJ, KeyCode::KeyK, KeyCode::KeyL,
+    KeyCode::KeyM, KeyCode::KeyN, KeyCode::KeyO, KeyCode::KeyP, KeyCode::KeyQ, KeyCode::KeyR,
+    KeyCode::KeyS, KeyCode::KeyT, KeyCode::KeyU, KeyCode::KeyV, KeyCode::KeyW, KeyCode::KeyX,
+    KeyCode::KeyY, KeyCode::KeyZ,
+    KeyCode::Digit0, KeyCode::Digit1, KeyCode::Digit2, KeyCode::Digit3, KeyCode::Digit4,
+    KeyCode::Digit5, KeyCode::Digit6, KeyCode::Digit7, KeyCode::Digit8, KeyCode::Digit9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::Space, KeyCode::ShiftLeft, KeyCode::ShiftRight, KeyCode::ControlLeft,
+    KeyCode::ControlRight, KeyCode::AltLeft, KeyCode::Tab, KeyCode::Escape, KeyCode::Enter,
+    KeyCode::Backspace, KeyCode::ArrowUp, KeyCode::ArrowDown, KeyCode::ArrowLeft, KeyCode::ArrowRight,
+    KeyCode::Slash, KeyCode::Backquote,
+];
+
+const BINDABLE_MOUSE_BUTTONS: [MouseButton; 5] = [
+    MouseButton::Left,
+    MouseButton::Right,
+    MouseButton::Middle,
+    MouseButton::Back,
+    MouseButton::Forward,
+];
+
+impl Binding {
+    // Bindings are stored by their debug name, e.g. `Key(KeyW)` or `Mouse(Left)`
+    fn from_name(name: &str) -> Option<Binding> {
+        BINDABLE_KEYS
+            .into_iter()
+            .map(Binding::Key)
+            .chain(BINDABLE_MOUSE_BUTTONS.into_iter().map(Binding::Mouse))
+            .find(|binding| format!("{:?}", binding) == name)
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Binding::Key(key) => format!("{:?}", key),
+            Binding::Mouse(button) => format!("Mouse {:?}", button),
+        }
+    }
+
+    fn pressed(&self, keyboard_input: &ButtonInput<KeyCode>, mouse_button: &ButtonInput<MouseButton>) -> bool {
+        match self {
+            Binding::Key(key) => keyboard_input.pressed(*key),
+            Binding::Mouse(button) => mouse_button.pressed(*button),
+        }
+    }
+
+    fn just_pressed(&self, keyboard_input: &ButtonInput<KeyCode>, mouse_button: &ButtonInput<MouseButton>) -> bool {
+        match self {
+            Binding::Key(key) => keyboard_input.just_pressed(*key),
+            Binding::Mouse(button) => mouse_button.just_pressed(*button),
+        }
+    }
+}
+
+// One binding per action, loaded from the bindings file in the config directory
+#[derive(Resource, Clone, PartialEq)]
+struct InputBindings {
+    bindings: HashMap<InputAction, Binding>,
+}
+
+impl Default for InputBindings {
+    fn default() -> Self {
+        let bindings = HashMap::from([
+            (InputAction::MoveForward, Binding::Key(KeyCode::KeyW)),
+            (InputAction::MoveBack, Binding::Key(KeyCode::KeyS)),
+            (InputAction::MoveLeft, Binding::Key(KeyCode::KeyA)),
+            (InputAction::MoveRight, Binding::Key(KeyCode::KeyD)),
+            (InputAction::Jump, Binding::Key(KeyCode::Space)),
+            (InputAction::Descend, Binding::Key(KeyCode::ShiftLeft)),
+            (InputAction::Mine, Binding::Mouse(MouseButton::Left)),
+            (InputAction::Place, Binding::Mouse(MouseButton::Right)),
+            (InputAction::SelectSlot(1), Binding::Key(KeyCode::Digit1)),
+            (InputAction::SelectSlot(2), Binding::Key(KeyCode::Digit2)),
+            (InputAction::SelectSlot(3), Binding::Key(KeyCode::Digit3)),
+            (InputAction::SelectSlot(4), Binding::Key(KeyCode::Digit4)),
+            (InputAction::SelectSlot(5), Binding::Key(KeyCode::Digit5)),
+            (InputAction::SelectSlot(6), Binding::Key(KeyCode::Digit6)),
+            (InputAction::SelectSlot(7), Binding::Key(KeyCode::Digit7)),
+            (InputAction::SelectSlot(8), Binding::Key(KeyCode::Digit8)),
+            (InputAction::SelectSlot(9), Binding::Key(KeyCode::Digit9)),
+            (InputAction::SelectSlot(10), Binding::Key(KeyCode::Digit0)),
+            (InputAction::NextSlot, Binding::Key(KeyCode::KeyX)),
+            (InputAction::PreviousSlot, Binding::Key(KeyCode::KeyZ)),
+            (InputAction::ToggleAiDebug, Binding::Key(KeyCode::F3)),
+            (InputAction::CycleCamera, Binding::Key(KeyCode::F5)),
+            (InputAction::OpenConsole, Binding::Key(KeyCode::Slash)),
+            (InputAction::OpenCrafting, Binding::Key(KeyCode::KeyC)),
+            (InputAction::Interact, Binding::Key(KeyCode::KeyF)),
+            (InputAction::Drop, Binding::Key(KeyCode::KeyQ)),
+            (InputAction::Pause, Binding::Key(KeyCode::Escape)),
+        ]);
+        Self { bindings }
+    }
+}
+
+impl InputBindings {
+    fn path() -> PathBuf {
+        config_dir().join("bindings.cfg")
+    }
+
+    // `Action = Binding` per line, unknown lines keep the default binding
+    fn load() -> InputBindings {
+        let mut input_bindings = InputBindings::default();
+        let Ok(contents) = std::fs::read_to_string(Self::path()) else {
+            return input_bindings;
+        };
+
+        for line in contents.lines() {
+            let Some((action, binding)) = line.split_once('=') else {
+                continue;
+            };
+            let action = InputAction::ALL
+                .into_iter()
+                .find(|candidate| format!("{:?}", candidate) == action.trim());
+            if let (Some(action), Some(binding)) = (action, Binding::from_name(binding.trim())) {
+                input_bindings.bindings.insert(action, binding);
+            }
+        }
+
+        input_bindings
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut out = String::new();
+        for action in InputAction::ALL {
+            if let Some(binding) = self.bindings.get(&action) {
+                let _ = writeln!(out, "{:?} = {:?}", action, binding);
+            }
+        }
+
+        std::fs::create_dir_all(config_dir())?;
+        std::fs::write(Self::path(), out)
+    }
+
+    // Actions sharing a binding with another action
+    fn conflicts(&self) -> Vec<(InputAction, InputAction)> {
+        let mut conflicts = Vec::new();
+        for (i, first) in InputAction::ALL.iter().enumerate() {
+            for second in &InputAction::ALL[i + 1..] {
+                if self.bindings.contains_key(first) && self.bindings.get(first) == self.bindings.get(second) {
+                    conflicts.push((*first, *second));
+                }
+            }
+        }
+        conflicts
+    }
+}
+
+// Fixed gamepad layout, sticks are read separately into ActionAxes
+const GAMEPAD_BINDINGS: [(GamepadButton, InputAction); 12] = [
+    (GamepadButton::South, InputAction::Jump),
+    (GamepadButton::East, InputAction::Descend),
+    (GamepadButton::RightTrigger2, InputAction::Mine),
+    (GamepadButton::LeftTrigger2, InputAction::Place),
+    (GamepadButton::RightTrigger, InputAction::NextSlot),
+    (GamepadButton::LeftTrigger, InputAction::PreviousSlot),
+    (GamepadButton::Start, InputAction::Pause),
+    (GamepadButton::Select, InputAction::ToggleAiDebug),
+    (GamepadButton::DPadUp, InputAction::CycleCamera),
+    (GamepadButton::North, InputAction::OpenCrafting),
+    (GamepadButton::West, InputAction::Interact),
+    (GamepadButton::DPadDown, InputAction::Drop),
+];
+
+// Analog input for this frame, x is right and y is forward or up
+#[derive(Resource, Default)]
+struct ActionAxes {
+    movement: Vec2,
+    look: Vec2,
+}
+
+// Radial deadzone that still reaches full range at the edge
+fn apply_deadzone(stick: Vec2, deadzone: f32) -> Vec2 {
+    let length = stick.length();
+    if length <= deadzone {
+        return Vec2::ZERO;
+    }
+    let scaled = ((length - deadzone) / (1.0 - deadzone).max(f32::EPSILON)).min(1.0);
+    stick / length * scaled
+}
+
+// Turn the raw keyboard, mouse and gamepad state into actions for this frame
+fn update_actions(
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+    gamepads: Query<&Gamepad>,
+    input_bindings: Res<InputBindings>,
+    game_settings: Res<GameSettings>,
+    mut actions: ResMut<ButtonInput<InputAction>>,
+    mut action_axes: ResMut<ActionAxes>,
+    console: Res<ConsoleState>,
+    game_ui: Res<GameUI>,
+) {
+    actions.clear();
+
+    *action_axes = ActionAxes::default();
+
+    // Typing in the console shouldn't move or dig
+    if console.open {
+        actions.release_all();
+        return;
+    }
+
+    // Clicking around the crafting or chest window shouldn't move or dig, only the action that closes it works
+    let closing_action = if game_ui.show_crafting {
+        Some(InputAction::OpenCrafting)
+    } else if game_ui.open_chest.is_some() {
+        Some(InputAction::Interact)
+    } else {
+        None
+    };
+    let window_open = closing_action.is_some();
+
+    for gamepad in gamepads.iter().filter(|_| !window_open) {
+        action_axes.movement += apply_deadzone(gamepad.left_stick(), game_settings.gamepad_deadzone);
+        action_axes.look += apply_deadzone(gamepad.right_stick(), game_settings.gamepad_deadzone);
+    }
+    action_axes.movement = action_axes.movement.clamp_length_max(1.0);
+    action_axes.look = action_axes.look.clamp_length_max(1.0);
+
+    for action in InputAction::ALL {
+        if window_open && Some(action) != closing_action {
+            actions.release(action);
+            continue;
+        }
+
+        // A new press has to start this frame, so keys still held from a menu or the console are ignored
+        let held = actions.pressed(action);
+        let binding = input_bindings.bindings.get(&action);
+        let pressed = binding.is_some_and(|binding| {
+            if held {
+                binding.pressed(&keyboard_input, &mouse_button)
+            } else {
+                binding.just_pressed(&keyboard_input, &mouse_button)
+            }
+        }) || GAMEPAD_BINDINGS.iter().any(|(button, bound)| {
+            *bound == action && gamepads.iter().any(|gamepad| {
+                if held { gamepad.pressed(*button) } else { gamepad.just_pressed(*button) }
+            })
+        });
+        if pressed {
+            actions.press(action);
+        } else {
+            actions.release(action);
+        }
+    }
+}
+
+// Resource for tracking player stats
+#[derive(Resource)]
+struct PlayerStats {
+    pub health: f32,
+    pub max_health: f32,
+    pub breath: f32,
+    pub inventory: HashMap<BlockType, u32>,
+}
+
+// Resource for game UI
+#[derive(Resource)]
+struct GameUI {
+    show_debug: bool,
+    show_crosshair: bool,
+    show_ai_debug: bool,
+    show_settings: bool,
+    show_crafting: bool,
+    open_chest: Option<(i32, i32, i32)>,
+    rebinding: Option<InputAction>,
+}
+
+// Resource for game settings
+#[derive(Resource, Clone, Copy, PartialEq)]
+struct GameSettings {
+    pub render_distance: i32,
+    pub gravity_enabled: bool,
+    pub difficulty: Difficulty,
+    pub game_mode: GameMode,
+    pub day_length: f32, // Seconds for a full day and night
+    pub objective: Objective,
+    pub mouse_sensitivity: f32,
+    pub invert_y: bool,
+    pub fov: f32, // Vertical field of view in degrees
+    pub vsync: bool,
+    pub window_mode: WindowModeSetting,
+    pub master_volume: f32,
+    pub gamepad_deadzone: f32,
+    pub gamepad_look_speed: f32, // Radians per second at full stick
+    pub window_width: f32,
+    pub window_height: f32,
+    pub player_speed: f32, // Walking and flying speed in blocks per second
+}
+
+impl Default for GameSettings {
+    fn default() -> Self {
+        Self {
+            render_distance: 10,
+            gravity_enabled: true,
+            difficulty: Difficulty::Normal,
+            game_mode: GameMode::Survival,
+            day_length: 600.0,
+            objective: Objective::SurviveNights(3),
+            mouse_sensitivity: MOUSE_SENSITIVITY,
+            invert_y: false,
+            fov: 70.0,
+            vsync: true,
+            window_mode: WindowModeSetting::Windowed,
+            master_volume: 1.0,
+            gamepad_deadzone: 0.15,
+            gamepad_look_speed: 3.0,
+            window_width: 1280.0,
+            window_height: 720.0,
+            player_speed: PLAYER_SPEED,
+        }
+    }
+}
+
+// Pull a loaded setting back into the range its slider allows
+fn clamp_setting<T: PartialOrd + Copy>(value: T, range: &RangeInclusive<T>, default: T) -> T {
+    if value < *range.start() {
+        *range.start()
+    } else if value > *range.end() {
+        *range.end()
+    } else if value >= *range.start() {
+        value
+    } else {
+        // Only NaN compares false both ways
+        default
+    }
+}
+
+impl GameSettings {
+    // Slider ranges, also used to keep hand-edited settings files sane
+    const RENDER_DISTANCE_RANGE: RangeInclusive<i32> = 2..=32;
+    const DAY_LENGTH_RANGE: RangeInclusive<f32> = 60.0..=1800.0;
+    const MOUSE_SENSITIVITY_RANGE: RangeInclusive<f32> = 0.0005..=0.01;
+    const FOV_RANGE: RangeInclusive<f32> = 50.0..=110.0;
+    const MASTER_VOLUME_RANGE: RangeInclusive<f32> = 0.0..=1.0;
+    const GAMEPAD_DEADZONE_RANGE: RangeInclusive<f32> = 0.0..=0.5;
+    const GAMEPAD_LOOK_SPEED_RANGE: RangeInclusive<f32> = 0.5..=8.0;
+    const WINDOW_WIDTH_RANGE: RangeInclusive<f32> = 640.0..=3840.0;
+    const WINDOW_HEIGHT_RANGE: RangeInclusive<f32> = 360.0..=2160.0;
+    const PLAYER_SPEED_RANGE: RangeInclusive<f32> = 2.0..=12.0;
+
+    fn path() -> PathBuf {
+        config_dir().join("settings.cfg")
+    }
+
+    // `key = value` per line, anything missing or unreadable keeps its default
+    fn load() -> GameSettings {
+        let mut settings = GameSettings::default();
+        let Ok(contents) = std::fs::read_to_string(Self::path()) else {
+            return settings;
+        };
+
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim();
+
+            match key.trim() {
+                "render_distance" => settings.render_distance = value.parse().unwrap_or(settings.render_distance),
+                "day_length" => settings.day_length = value.parse().unwrap_or(settings.day_length),
+                "mouse_sensitivity" => settings.mouse_sensitivity = value.parse().unwrap_or(settings.mouse_sensitivity),
+                "invert_y" => settings.invert_y = value.parse().unwrap_or(settings.invert_y),
+                "fov" => settings.fov = value.parse().unwrap_or(settings.fov),
+                "vsync" => settings.vsync = value.parse().unwrap_or(settings.vsync),
+                "window_mode" => {
+                    settings.window_mode = WindowModeSetting::ALL
+                        .into_iter()
+                        .find(|mode| format!("{:?}", mode) == value)
+                        .unwrap_or(settings.window_mode);
+                }
+                "master_volume" => settings.master_volume = value.parse().unwrap_or(settings.master_volume),
+                "gamepad_deadzone" => settings.gamepad_deadzone = value.parse().unwrap_or(settings.gamepad_deadzone),
+                "gamepad_look_speed" => settings.gamepad_look_speed = value.parse().unwrap_or(settings.gamepad_look_speed),
+                "window_width" => settings.window_width = value.parse().unwrap_or(settings.window_width),
+                "window_height" => settings.window_height = value.parse().unwrap_or(settings.window_height),
+                "player_speed" => settings.player_speed = value.parse().unwrap_or(settings.player_speed),
+                _ => {}
+            }
+        }
+
+        settings.clamped()
+    }
+
+    fn clamped(self) -> GameSettings {
+        let default = GameSettings::default();
+        GameSettings {
+            render_distance: clamp_setting(self.render_distance, &Self::RENDER_DISTANCE_RANGE, default.render_distance),
+            day_length: clamp_setting(self.day_length, &Self::DAY_LENGTH_RANGE, default.day_length),
+            mouse_sensitivity: clamp_setting(self.mouse_sensitivity, &Self::MOUSE_SENSITIVITY_RANGE, default.mouse_sensitivity),
+            fov: clamp_setting(self.fov, &Self::FOV_RANGE, default.fov),
+            master_volume: clamp_setting(self.master_volume, &Self::MASTER_VOLUME_RANGE, default.master_volume),
+            gamepad_deadzone: clamp_setting(self.gamepad_deadzone, &Self::GAMEPAD_DEADZONE_RANGE, default.gamepad_deadzone),
+            gamepad_look_speed: clamp_setting(self.gamepad_look_speed, &Self::GAMEPAD_LOOK_SPEED_RANGE, default.gamepad_look_speed),
+            window_width: clamp_setting(self.window_width, &Self::WINDOW_WIDTH_RANGE, default.window_width),
+            window_height: clamp_setting(self.window_height, &Self::WINDOW_HEIGHT_RANGE, default.window_height),
+            player_speed: clamp_setting(self.player_speed, &Self::PLAYER_SPEED_RANGE, default.player_speed),
+            ..self
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut out = String::new();
+        let _ = writeln!(out, "render_distance = {}", self.render_distance);
+        let _ = writeln!(out, "day_length = {}", self.day_length);
+        let _ = writeln!(out, "mouse_sensitivity = {}", self.mouse_sensitivity);
+        let _ = writeln!(out, "invert_y = {}", self.invert_y);
+        let _ = writeln!(out, "fov = {}", self.fov);
+        let _ = writeln!(out, "vsync = {}", self.vsync);
+        let _ = writeln!(out, "window_mode = {:?}", self.window_mode);
+        let _ = writeln!(out, "master_volume = {}", self.master_volume);
+        let _ = writeln!(out, "gamepad_deadzone = {}", self.gamepad_deadzone);
+        let _ = writeln!(out, "gamepad_look_speed = {}", self.gamepad_look_speed);
+        let _ = writeln!(out, "window_width = {}", self.window_width);
+        let _ = writeln!(out, "window_height = {}", self.window_height);
+        let _ = writeln!(out, "player_speed = {}", self.player_speed);
+
+        std::fs::create_dir_all(config_dir())?;
+        std::fs::write(Self::path(), out)
+    }
+
+    fn present_mode(&self) -> PresentMode {
+        if self.vsync { PresentMode::AutoVsync } else { PresentMode::AutoNoVsync }
+    }
+
+    fn projection(&self) -> PerspectiveProjection {
+        PerspectiveProjection {
+            fov: self.fov.to_radians(),
+            far: self.render_distance as f32 * RENDER_DISTANCE_SCALE,
+            ..default()
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum WindowModeSetting {
+    Windowed,
+    Borderless,
+    Fullscreen,
+}
+
+impl WindowModeSetting {
+    const ALL: [WindowModeSetting; 3] = [
+        WindowModeSetting::Windowed,
+        WindowModeSetting::Borderless,
+        WindowModeSetting::Fullscreen,
+    ];
+
+    fn window_mode(&self) -> WindowMode {
+        match self {
+            WindowModeSetting::Windowed => WindowMode::Windowed,
+            WindowModeSetting::Borderless => WindowMode::BorderlessFullscreen(MonitorSelection::Current),
+            WindowModeSetting::Fullscreen => WindowMode::Fullscreen(MonitorSelection::Current),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum GameMode {
+    Survival,
+    Creative,
+    Spectator,
+}
+
+impl GameMode {
+    const ALL: [GameMode; 3] = [GameMode::Survival, GameMode::Creative, GameMode::Spectator];
+
+    fn from_name(name: &str) -> Option<GameMode> {
+        Self::ALL.into_iter().find(|mode| format!("{:?}", mode).eq_ignore_ascii_case(name))
+    }
+}
+
+// Crim only hunts players in survival
+fn crim_enabled(game_settings: Res<GameSettings>) -> bool {
+    game_settings.game_mode == GameMode::Survival
+}
+
+// Switching out of survival sends every Crim away instead of leaving them frozen where they stood
+fn dismiss_crims(mut commands: Commands, game_settings: Res<GameSettings>, crim_query: Query<Entity, With<Crim>>) {
+    if game_settings.game_mode == GameMode::Survival {
+        return;
+    }
+    for entity in crim_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+}
+
+#[derive(States, Default, Debug, Clone, PartialEq, Eq, Hash)]
+enum GameState {
+    #[default]
+    MainMenu,
+    Loading,
+    Playing,
+    Paused,
+    GameOver,
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Difficulty {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Difficulty {
+    fn max_crims(&self) -> usize {
+        match self {
+            Difficulty::Easy => 1,
+            Difficulty::Normal => 3,
+            Difficulty::Hard => 6,
+        }
+    }
+
+    fn crim_speed_multiplier(&self) -> f32 {
+        match self {
+            Difficulty::Easy => 0.8,
+            Difficulty::Normal => 1.0,
+            Difficulty::Hard => 1.25,
+        }
+    }
+}
+
+// Resource describing the space the player is standing in
+#[derive(Resource, Default)]
+struct Shelter {
+    sealed: bool,
+    volume: usize,
+    weakest_wall: Option<((i32, i32, i32), BlockType)>,
+}
+
+// Flood fill the air around `start`. The space is sealed if the fill runs out of
+// cells before reaching SHELTER_MAX_VOLUME or falling out of the world.
+fn analyze_shelter(game_world: &GameWorld, start: (i32, i32, i32)) -> Shelter {
+    if game_world.is_solid(start) {
+        return Shelter::default();
+    }
+
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    let mut weakest_wall: Option<((i32, i32, i32), BlockType)> = None;
+
+    while let Some((x, y, z)) = queue.pop_front() {
+        if visited.len() > SHELTER_MAX_VOLUME || y < 0 {
+            return Shelter {
+                sealed: false,
+                volume: visited.len(),
+                weakest_wall: None,
+            };
+        }
+
+        let neighbors = [
+            (x + 1, y, z),
+            (x - 1, y, z),
+            (x, y, z + 1),
+            (x, y, z - 1),
+            (x, y + 1, z),
+            (x, y - 1, z),
+        ];
+
+        for (i, cell) in neighbors.into_iter().enumerate() {
+            if let Some(block_type) = game_world.block_type(cell).filter(|_| game_world.is_solid(cell)) {
+                // Only side walls are candidates, Crim can't dig up through the floor
+                let is_side = i < 4;
+                let weaker = weakest_wall.is_none_or(|(_, weakest)| block_type.hardness() < weakest.hardness());
+                if is_side && weaker {
+                    weakest_wall = Some((cell, block_type));
+                }
+            } else if visited.insert(cell) {
+                queue.push_back(cell);
+            }
+        }
+    }
+
+    Shelter {
+        sealed: true,
+        volume: visited.len(),
+        weakest_wall,
+    }
+}
+
+fn shelter_analysis(
+    player_query: Query<&Transform, With<Player>>,
+    game_world: Res<GameWorld>,
+    mut shelter: ResMut<Shelter>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    *shelter = analyze_shelter(&game_world, block_coords(player_transform.translation));
+}
+
+// Resource for the Crim spawner
+#[derive(Resource)]
+struct CrimSpawner {
+    timer: f32,
+}
+
+// Resource for the day/night cycle. `time` runs from 0 to 1, starting at midnight.
+#[derive(Resource)]
+struct TimeOfDay {
+    time: f32,
+    day: u32,
+}
+
+impl Default for TimeOfDay {
+    fn default() -> Self {
+        // Start the first day in the morning
+        Self { time: 0.3, day: 0 }
+    }
+}
+
+impl TimeOfDay {
+    // Height of the sun, -1 at midnight and 1 at noon
+    fn sun_elevation(&self) -> f32 {
+        ((self.time - 0.25) * std::f32::consts::TAU).sin()
+    }
+
+    // 0 at night, 1 during the day, blending over dawn and dusk
+    fn daylight(&self) -> f32 {
+        ((self.sun_elevation() + 0.1) / 0.3).clamp(0.0, 1.0)
+    }
+
+    fn night_factor(&self) -> f32 {
+        1.0 - self.daylight()
+    }
+
+    fn clock(&self) -> (u32, u32) {
+        let minutes = (self.time * 24.0 * 60.0) as u32;
+        (minutes / 60, minutes % 60)
+    }
+}
+
+// Goal for a survival run, picked from the main menu
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum Objective {
+    SurviveNights(u32),
+    CollectOre(u32),
+    ReachBeacon,
+}
+
+impl Objective {
+    const CHOICES: [Objective; 3] = [
+        Objective::SurviveNights(3),
+        Objective::CollectOre(10),
+        Objective::ReachBeacon,
+    ];
+
+    fn describe(&self) -> String {
+        match self {
+            Objective::SurviveNights(nights) => format!("Survive {} nights", nights),
+            Objective::CollectOre(ore) => format!("Collect {} ore", ore),
+            Objective::ReachBeacon => "Reach the beacon".to_string(),
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum RunOutcome {
+    InProgress,
+    Won,
+    Died,
+}
+
+// Resource tracking the current run for scoring
+#[derive(Resource, Clone)]
+struct RunScore {
+    time_survived: f32,
+    blocks_mined: u32,
+    ore_collected: u32,
+    close_calls: u32,
+    start_day: u32,
+    outcome: RunOutcome,
+    cheated: bool, // A console command changed the world or the player, so the score doesn't count
+    left_survival: bool, // Part of the run was played outside Survival
+}
+
+impl Default for RunScore {
+    fn default() -> Self {
+        Self {
+            time_survived: 0.0,
+            blocks_mined: 0,
+            ore_collected: 0,
+            close_calls: 0,
+            start_day: 0,
+            outcome: RunOutcome::InProgress,
+            cheated: false,
+            left_survival: false,
+        }
+    }
+}
+
+impl RunScore {
+    fn total(&self) -> u32 {
+        let bonus = if self.outcome == RunOutcome::Won { WIN_BONUS_SCORE } else { 0 };
+        self.time_survived as u32
+            + self.blocks_mined * BLOCK_MINED_SCORE
+            + self.close_calls * CLOSE_CALL_SCORE
+            + bonus
+    }
+
+    // Only runs played entirely in Survival without the console go on the high score table
+    fn counts(&self) -> bool {
+        !self.cheated && !self.left_survival
+    }
+
+    fn nights_survived(&self, time_of_day: &TimeOfDay) -> u32 {
+        // A night counts once the following morning arrives
+        let days = time_of_day.day.saturating_sub(self.start_day);
+        if time_of_day.time >= 0.25 { days } else { days.saturating_sub(1) }
+    }
+}
+
+// Local high score table, best first
+#[derive(Resource, Default)]
+struct HighScores {
+    entries: Vec<(u32, String)>,
+    last_rank: Option<usize>,
+}
+
+impl HighScores {
+    fn path() -> PathBuf {
+        data_dir().join("highscores.txt")
+    }
+
+    // Each line is a score followed by a short description of the run
+    fn load() -> HighScores {
+        let entries = std::fs::read_to_string(Self::path())
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter_map(|line| {
+                        let (score, description) = line.split_once(' ')?;
+                        Some((score.parse().ok()?, description.to_string()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        HighScores { entries, last_rank: None }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let mut out = String::new();
+        for (score, description) in &self.entries {
+            let _ = writeln!(out, "{} {}", score, description);
+        }
+        std::fs::create_dir_all(data_dir())?;
+        std::fs::write(Self::path(), out)
+    }
+
+    // Insert a score and return its rank if it made the table
+    fn record(&mut self, score: u32, description: String) -> Option<usize> {
+        let rank = self.entries.iter().position(|(existing, _)| score > *existing).unwrap_or(self.entries.len());
+        if rank >= MAX_HIGH_SCORES {
+            return None;
+        }
+        self.entries.insert(rank, (score, description));
+        self.entries.truncate(MAX_HIGH_SCORES);
+        Some(rank)
+    }
+}
+
+// The glowing pillar the player has to reach for the beacon objective
+#[derive(Component)]
+struct Beacon {
+    cell: (i32, i32, i32), // Where it stands, kept in the save so the goal doesn't move
+}
+
+// Resource for loading a saved world on the next run
+#[derive(Resource, Default)]
+struct WorldSave {
+    load_requested: bool,
+}
+
+#[derive(Event)]
+struct SaveGameEvent;
+
+// Everything written to the save file
+struct SaveData {
+    seed: u64,
+    time_of_day: f32,
+    day: u32,
+    player_position: Vec3,
+    health: f32,
+    breath: f32,
+    game_mode: GameMode,
+    objective: Objective,
+    beacon: Option<(i32, i32, i32)>,
+    inventory: HashMap<BlockType, u32>,
+    blocks: HashMap<(i32, i32, i32), BlockState>,
+    containers: HashMap<(i32, i32, i32), Container>,
+    dropped_items: Vec<(Vec3, BlockType, u32, bool)>, // The flag marks freshly mined items
+    run_score: RunScore,
+}
+
+impl SaveData {
+    // One record per line: `seed`, `time`, `score`, `objective`, `beacon`, `player`, `stats`, `mode`, `item`, `block`,
+    // `stored` and `dropped`,
+    // blocks with properties add their bits at the end
+    fn write(&self, path: &Path) -> io::Result<()> {
+        let mut out = String::new();
+        let _ = writeln!(out, "seed {}", self.seed);
+        let _ = writeln!(out, "time {} {}", self.time_of_day, self.day);
+        let score = &self.run_score;
+        let _ = writeln!(
+            out,
+            "score {} {} {} {} {} {} {}",
+            score.time_survived,
+            score.blocks_mined,
+            score.ore_collected,
+            score.close_calls,
+            score.start_day,
+            score.cheated,
+            score.left_survival,
+        );
+        let objective = match self.objective {
+            Objective::SurviveNights(nights) => format!("nights {}", nights),
+            Objective::CollectOre(ore) => format!("ore {}", ore),
+            Objective::ReachBeacon => "beacon".to_string(),
+        };
+        let _ = writeln!(out, "objective {}", objective);
+        if let Some((x, y, z)) = self.beacon {
+            let _ = writeln!(out, "beacon {} {} {}", x, y, z);
+        }
+        let p = self.player_position;
+        let _ = writeln!(out, "player {} {} {}", p.x, p.y, p.z);
+        let _ = writeln!(out, "stats {} {}", self.health, self.breath);
+        let _ = writeln!(out, "mode {:?}", self.game_mode);
+        for (block_type, count) in &self.inventory {
+            let _ = writeln!(out, "item {:?} {}", block_type, count);
+        }
+        for ((x, y, z), state) in &self.blocks {
+            if state.properties == 0 {
+                let _ = writeln!(out, "block {} {} {} {:?}", x, y, z, state.block_type);
+            } else {
+                let _ = writeln!(out, "block {} {} {} {:?} {}", x, y, z, state.block_type, state.properties);
+            }
+        }
+        for ((x, y, z), container) in &self.containers {
+            for (block_type, count) in &container.items {
+                let _ = writeln!(out, "stored {} {} {} {:?} {}", x, y, z, block_type, count);
+            }
+        }
+        for (position, block_type, count, mined) in &self.dropped_items {
+            let _ = writeln!(out, "dropped {} {} {} {:?} {} {}", position.x, position.y, position.z, block_type, count, mined);
+        }
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        std::fs::write(path, out)
+    }
+
+    fn read(path: &Path) -> io::Result<SaveData> {
+        let contents = std::fs::read_to_string(path)?;
+        let invalid = |line: &str| io::Error::new(io::ErrorKind::InvalidData, format!("bad save line: {}", line));
+
+        let mut save = SaveData {
+            seed: 0,
+            time_of_day: TimeOfDay::default().time,
+            day: 0,
+            player_position: Vec3::new(0.0, WORLD_HEIGHT as f32 + 1.0, 0.0),
+            health: 100.0,
+            breath: MAX_BREATH,
+            game_mode: GameMode::Survival,
+            objective: GameSettings::default().objective,
+            beacon: None,
+            inventory: HashMap::new(),
+            blocks: HashMap::new(),
+            containers: HashMap::new(),
+            dropped_items: Vec::new(),
+            run_score: RunScore::default(),
+        };
+
+        for line in contents.lines() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                ["seed", seed] => save.seed = seed.parse().map_err(|_| invalid(line))?,
+                ["time", time, day] => {
+                    save.time_of_day = time.parse().map_err(|_| invalid(line))?;
+                    save.day = day.parse().map_err(|_| invalid(line))?;
+                }
+                ["score", time_survived, blocks_mined, ore_collected, close_calls, start_day, cheated, left_survival] => {
+                    save.run_score = RunScore {
+                        time_survived: time_survived.parse().map_err(|_| invalid(line))?,
+                        blocks_mined: blocks_mined.parse().map_err(|_| invalid(line))?,
+                        ore_collected: ore_collected.parse().map_err(|_| invalid(line))?,
+                        close_calls: close_calls.parse().map_err(|_| invalid(line))?,
+                        start_day: start_day.parse().map_err(|_| invalid(line))?,
+                        cheated: cheated.parse().map_err(|_| invalid(line))?,
+                        left_survival: left_survival.parse().map_err(|_| invalid(line))?,
+                        outcome: RunOutcome::InProgress,
+                    };
+                }
+                ["objective", "nights", nights] => {
+                    save.objective = Objective::SurviveNights(nights.parse().map_err(|_| invalid(line))?);
+                }
+                ["objective", "ore", ore] => save.objective = Objective::CollectOre(ore.parse().map_err(|_| invalid(line))?),
+                ["objective", "beacon"] => save.objective = Objective::ReachBeacon,
+                ["beacon", x, y, z] => {
+                    save.beacon = Some((
+                        x.parse().map_err(|_| invalid(line))?,
+                        y.parse().map_err(|_| invalid(line))?,
+                        z.parse().map_err(|_| invalid(line))?,
+                    ));
+                }
+                ["player", x, y, z] => {
+                    save.player_position = Vec3::new(
+                        x.parse().map_err(|_| invalid(line))?,
+                        y.parse().map_err(|_| invalid(line))?,
+                        z.parse().map_err(|_| invalid(line))?,
+                    );
+                }
+                ["stats", health, breath] => {
+                    save.health = health.parse().map_err(|_| invalid(line))?;
+                    save.breath = breath.parse().map_err(|_| invalid(line))?;
+                }
+                ["mode", name] => save.game_mode = GameMode::from_name(name).ok_or_else(|| invalid(line))?,
+                ["item", name, count] => {
+                    let block_type = BlockType::from_name(name).ok_or_else(|| invalid(line))?;
+                    save.inventory.insert(block_type, count.parse().map_err(|_| invalid(line))?);
+                }
+                ["block", x, y, z, name, properties @ ..] if properties.len() <= 1 => {
+                    let pos = (
+                        x.parse().map_err(|_| invalid(line))?,
+                        y.parse().map_err(|_| invalid(line))?,
+                        z.parse().map_err(|_| invalid(line))?,
+                    );
+                    let block_type = BlockType::from_name(name).ok_or_else(|| invalid(line))?;
+                    let properties = match properties {
+                        [bits] => bits.parse().map_err(|_| invalid(line))?,
+                        _ => 0,
+                    };
+                    save.blocks.insert(pos, BlockState { block_type, properties });
+                }
+                ["stored", x, y, z, name, count] => {
+                    let pos = (
+                        x.parse().map_err(|_| invalid(line))?,
+                        y.parse().map_err(|_| invalid(line))?,
+                        z.parse().map_err(|_| invalid(line))?,
+                    );
+                    let block_type = BlockType::from_name(name).ok_or_else(|| invalid(line))?;
+                    let count = count.parse().map_err(|_| invalid(line))?;
+                    save.containers.entry(pos).or_default().items.insert(block_type, count);
+                }
+                ["dropped", x, y, z, name, count, mined] => {
+                    let position = Vec3::new(
+                        x.parse().map_err(|_| invalid(line))?,
+                        y.parse().map_err(|_| invalid(line))?,
+                        z.parse().map_err(|_| invalid(line))?,
+                    );
+                    let block_type = BlockType::from_name(name).ok_or_else(|| invalid(line))?;
+                    let count = count.parse().map_err(|_| invalid(line))?;
+                    save.dropped_items.push((position, block_type, count, mined.parse().map_err(|_| invalid(line))?));
+                }
+                [] => {}
+                _ => return Err(invalid(line)),
+            }
+        }
+
+        Ok(save)
+    }
+}
+
+// Systems
+fn setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut game_world: ResMut<GameWorld>,
+    mut player_stats: ResMut<PlayerStats>,
+    mut game_settings: ResMut<GameSettings>,
+    block_assets: Res<BlockAssets>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut world_save: ResMut<WorldSave>,
+    mut run_score: ResMut<RunScore>,
+) {
+    // Log the render distance
+    println!("Initializing world with render distance: {}", game_settings.render_distance);
+    // First-person camera will be attached to the player in the camera_follow system
+
+    let save = if world_save.load_requested {
+        SaveData::read(&save_path())
+            .inspect_err(|err| println!("Failed to load world, generating a new one: {}", err))
+            .ok()
+    } else {
+        None
+    };
+    world_save.load_requested = false;
+
+    let (player_position, beacon) = match save {
+        Some(save) => {
+            // Restore the saved world
+            game_world.seed = save.seed;
+            for (pos, state) in save.blocks {
+                game_world.blocks.insert(pos, state);
+                spawn_block(&mut commands, &block_assets, pos, state);
+            }
+            game_world.containers = save.containers;
+            for (position, block_type, count, mined) in save.dropped_items {
+                let item = spawn_dropped_item(&mut commands, &block_assets, position, block_type, count, Vec3::ZERO, 0.0);
+                if mined {
+                    commands.entity(item).insert(Mined);
+                }
+            }
+            player_stats.inventory = save.inventory;
+            player_stats.health = save.health.min(player_stats.max_health);
+            player_stats.breath = save.breath.min(MAX_BREATH);
+            game_settings.game_mode = save.game_mode;
+            // The run keeps the goal it started with, whatever the menu has picked since
+            game_settings.objective = save.objective;
+            *time_of_day = TimeOfDay {
+                time: save.time_of_day,
+                day: save.day,
+            };
+            *run_score = save.run_score;
+            (save.player_position, save.beacon)
+        }
+        None => {
+            // Add starting materials to player inventory
+            player_stats.inventory.insert(BlockType::Dirt, 20);
+            player_stats.inventory.insert(BlockType::Stone, 10);
+            *time_of_day = TimeOfDay::default();
+            *run_score = RunScore {
+                start_day: time_of_day.day,
+                ..default()
+            };
+
+            // Generate world
+            game_world.seed = rand::random();
+            generate_world(&mut commands, &block_assets, &mut game_world);
+            (Vec3::new(0.0, WORLD_HEIGHT as f32 + 1.0, 0.0), None)
+        }
+    };
+    game_world.relight_all();
+
+    // Put the beacon in a far corner of the world, or back where it stood in a saved run
+    if game_settings.objective == Objective::ReachBeacon {
+        let cell = beacon.unwrap_or_else(|| {
+            let mut rng = rand::rng();
+            let corner = WORLD_SIZE - 3;
+            let x = if rng.random_bool(0.5) { corner } else { -corner };
+            let z = if rng.random_bool(0.5) { corner } else { -corner };
+            let ground = game_world.blocks.keys()
+                .filter(|(bx, _, bz)| *bx == x && *bz == z)
+                .map(|(_, by, _)| *by)
+                .max()
+                .unwrap_or(0);
+            (x, ground + 1, z)
+        });
+
+        commands.spawn((
+            Mesh3d(meshes.add(Cuboid::new(0.6, 8.0, 0.6))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(0.3, 1.0, 1.0),
+                emissive: Color::srgb(0.3, 1.0, 1.0).into(),
+                ..default()
+            })),
+            Transform::from_translation(cell_center(cell) + Vec3::Y * 3.5),
+            Beacon { cell },
+        ));
+    }
+
+    // Spawn player with pickaxe
+    commands.spawn((
+        Mesh3d(meshes.add(Capsule3d::default())),
+        MeshMaterial3d(materials.add(Color::srgb(0.0, 0.0, 1.0))),
+        Transform::from_translation(player_position),
+        Player {
+            has_pickaxe: true,
+            velocity: Vec3::ZERO,
+            is_grounded: false,
+            selected_block_type: BlockType::Dirt,
+            mining_target: None,
+            mining_progress: 0.0,
+        },
+    ))
+    .with_children(|parent| {
+        // Attach first-person camera to player
+        parent.spawn((
+            Camera3d::default(),
+            Projection::Perspective(game_settings.projection()),
+            MainCamera,
+            Transform::from_translation(EYE_OFFSET),
+        ));
+        
+        // Add player arms/tool model visible in first person
+        parent.spawn((
+            Mesh3d(meshes.add(Cuboid::new(0.1, 0.1, 0.5))),
+            MeshMaterial3d(materials.add(Color::srgb(0.8, 0.6, 0.4))),
+            Transform::from_translation(EYE_OFFSET + ARM_OFFSET)
+                .with_rotation(Quat::from_rotation_x(-0.3)),
+            PlayerArm,
+        ));
+    });
+
+    // Spawn Crim (the monster)
+    spawn_crim(
+        &mut commands,
+        &mut meshes,
+        &mut materials,
+        Vec3::new(10.0, WORLD_HEIGHT as f32 + 1.0, 10.0),
+        CRIM_SPEED * game_settings.difficulty.crim_speed_multiplier(),
+    );
+
+    // Scatter some sheep over dry land, finding the top of every column once up front
+    let mut surface: HashMap<(i32, i32), (i32, BlockType)> = HashMap::new();
+    for (&(x, y, z), state) in game_world.blocks.iter() {
+        let top = surface.entry((x, z)).or_insert((y, state.block_type));
+        if y > top.0 {
+            *top = (y, state.block_type);
+        }
+    }
+
+    let mut rng = rand::rng();
+    let mut sheep_spawned = 0;
+    for _ in 0..SHEEP_SPAWN_ATTEMPTS {
+        if sheep_spawned == SHEEP_COUNT {
+            break;
+        }
+        let x = rng.random_range(-WORLD_SIZE + 2..WORLD_SIZE - 2);
+        let z = rng.random_range(-WORLD_SIZE + 2..WORLD_SIZE - 2);
+
+        let Some(&(ground, block_type)) = surface.get(&(x, z)) else {
+            continue;
+        };
+
+        if block_type == BlockType::Water {
+            continue;
+        }
+
+        spawn_sheep(&mut commands, &mut meshes, &mut materials, cell_center((x, ground + 1, z)));
+        sheep_spawned += 1;
+    }
+}
+
+fn spawn_sheep(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    position: Vec3,
+) {
+    commands.spawn((
+        Mesh3d(meshes.add(Cuboid::new(0.6, 0.6, 0.9))),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgb(0.95, 0.95, 0.9),
+            perceptual_roughness: 1.0,
+            ..default()
+        })),
+        Transform::from_translation(position),
+        Sheep {
+            velocity: Vec3::ZERO,
+            is_grounded: false,
+            wander_direction: Vec3::ZERO,
+            wander_timer: 0.0,
+            health: SHEEP_HEALTH,
+        },
+    ))
+    .with_children(|parent| {
+        // Head
+        parent.spawn((
+            Mesh3d(meshes.add(Cuboid::new(0.3, 0.3, 0.3))),
+            MeshMaterial3d(materials.add(Color::srgb(0.3, 0.25, 0.2))),
+            Transform::from_xyz(0.0, 0.2, -0.55),
+        ));
+    });
+}
+
+fn spawn_crim(
+    commands: &mut Commands,
+    meshes: &mut ResMut<Assets<Mesh>>,
+    materials: &mut ResMut<Assets<StandardMaterial>>,
+    position: Vec3,
+    speed: f32,
+) {
+    commands.spawn((
+        Mesh3d(meshes.add(Capsule3d::default())),
+        MeshMaterial3d(materials.add(StandardMaterial {
+            base_color: Color::srgb(1.0, 0.0, 0.0),
+            emissive: Color::srgb(0.8, 0.0, 0.0).into(),
+            ..default()
+        })),
+        Transform::from_translation(position),
+        Crim {
+            chase_timer: 0.0,
+            spotted_player: false,
+            velocity: Vec3::ZERO,
+            is_grounded: false,
+            break_target: None,
+            break_progress: 0.0,
+            speed,
+            near_player: false,
+            state: CrimState::Idle,
+            last_known_player_pos: None,
+            heard_noise: None,
+            target: None,
+            health: CRIM_MAX_HEALTH,
+        },
+    ))
+    .with_children(|parent| {
+        // Add glowing eyes
+        parent.spawn((
+            Mesh3d(meshes.add(Sphere::new(0.1))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(1.0, 1.0, 0.3),
+                emissive: Color::srgb(1.0, 1.0, 0.3).into(),
+                ..default()
+            })),
+            Transform::from_xyz(0.2, 0.3, -0.3),
+        ));
+        
+        parent.spawn((
+            Mesh3d(meshes.add(Sphere::new(0.1))),
+            MeshMaterial3d(materials.add(StandardMaterial {
+                base_color: Color::srgb(1.0, 1.0, 0.3),
+                emissive: Color::srgb(1.0, 1.0, 0.3).into(),
+                ..default()
+            })),
+            Transform::from_xyz(-0.2, 0.3, -0.3),
+        ));
+    });
+}
+
+fn generate_world(
+    commands: &mut Commands,
+    block_assets: &BlockAssets,
+    game_world: &mut ResMut<GameWorld>,
+) {
+    let mut rng = StdRng::seed_from_u64(game_world.seed);
+
+    // Generate terrain
+    for x in -WORLD_SIZE..WORLD_SIZE {
+        for z in -WORLD_SIZE..WORLD_SIZE {
+            let height = (rng.random::<f32>() * 3.0).floor() as i32;
+            
+            // Create ground layer with more varied terrain
+            for y in 0..=height {
+                // More varied terrain generation
+                let block_type = if y == height && height > 0 {
+                    if rng.random_bool(0.6) { BlockType::Grass } else { BlockType::Dirt }
+                } else if y == height && height == 0 {
+                    if rng.random_bool(0.7) { BlockType::Sand } else { BlockType::Dirt }
+                } else if y == 0 {
+                    if rng.random_bool(0.05) { BlockType::Obsidian } else { BlockType::Stone }
+                } else if y < height - 2 && rng.random_bool(0.05) {
+                    BlockType::Ore
+                } else if rng.random_bool(0.8) {
+                    BlockType::Stone
+                } else {
+                    BlockType::Dirt
+                };
+                
+                game_world.blocks.insert((x, y, z), block_type.into());
+                spawn_block(commands, block_assets, (x, y, z), block_type);
+            }
+        }
+    }
+
+    // Add some trees and structures
+    for _ in 0..20 {
+        let x = rng.random_range(-WORLD_SIZE+2..WORLD_SIZE-2);
+        let z = rng.random_range(-WORLD_SIZE+2..WORLD_SIZE-2);
+        
+        if let Some(base_height) = game_world.blocks.keys()
+            .filter(|(bx, _, bz)| *bx == x && *bz == z)
+            .map(|(_, by, _)| *by)
+            .max()
+        {
+            // Decide what to generate - trees or small structures
+            let structure_type = rng.random_range(0..10);
+            
+            match structure_type {
+                // Trees (70% chance)
+                0..=6 => {
+                    // Tree trunk
+                    for y in base_height + 1..base_height + 6 {
+                        game_world.blocks.insert((x, y, z), BlockType::Wood.into());
+                        spawn_block(commands, block_assets, (x, y, z), BlockType::Wood);
+                    }
+                    
+                    // Tree leaves
+                    let tree_top = base_height + 5;
+                    for leaf_x in x-2..=x+2 {
+                        for leaf_z in z-2..=z+2 {
+                            // Skip corners for a more natural shape
+                            if (leaf_x == x-2 || leaf_x == x+2) && (leaf_z == z-2 || leaf_z == z+2) {
+                                continue;
+                            }
+                            
+                            for leaf_y in tree_top-1..=tree_top+1 {
+                                // Skip if there's already a block here
+                                if game_world.blocks.contains_key(&(leaf_x, leaf_y, leaf_z)) {
+                                    continue;
+                                }
+                                
+                                // Add leaf block
+                                game_world.blocks.insert((leaf_x, leaf_y, leaf_z), BlockType::Grass.into());
+                                spawn_block(commands, block_assets, (leaf_x, leaf_y, leaf_z), BlockType::Grass);
+                            }
+                        }
+                    }
+                },
+                
+                // Stone pillar (20% chance)
+                7..=8 => {
+                    let height = rng.random_range(4..8);
+                    for y in base_height + 1..base_height + height {
+                        game_world.blocks.insert((x, y, z), BlockType::Stone.into());
+                        spawn_block(commands, block_assets, (x, y, z), BlockType::Stone);
+                        
+                        // Add some obsidian at the top
+                        if y == base_height + height - 1 {
+                            game_world.blocks.insert((x, y+1, z), BlockType::Obsidian.into());
+                            spawn_block(commands, block_assets, (x, y+1, z), BlockType::Obsidian);
+                        }
+                    }
+                },
+                
+                // Glass tower (10% chance)
+                9 => {
+                    let height = rng.random_range(3..6);
+                    for y in base_height + 1..base_height + height {
+                        game_world.blocks.insert((x, y, z), BlockType::Glass.into());
+                        spawn_block(commands, block_assets, (x, y, z), BlockType::Glass);
+                    }
+                },
+                
+                _ => {}
+            }
+        }
+    }
+}
+
+fn player_movement(
+    actions: Res<ButtonInput<InputAction>>,
+    action_axes: Res<ActionAxes>,
+    mut player_query: Query<(&mut Transform, &mut Player)>,
+    time: Res<Time>,
+    game_world: Res<GameWorld>,
+    game_settings: Res<GameSettings>,
+    camera_rig: Res<CameraRig>,
+) {
+    // The free camera takes over the movement keys
+    if camera_rig.mode == CameraMode::Free {
+        return;
+    }
+
+    let (mut player_transform, mut player) = player_query.single_mut();
+    
+    // The body carries the heading, pitch stays on the camera
+    let mut direction = Vec3::ZERO;
+    let forward = player_transform.forward();
+    let right = player_transform.right();
+    
+    // Get forward/backward movement in the XZ plane
+    let forward_xz = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
+    let right_xz = Vec3::new(right.x, 0.0, right.z).normalize_or_zero();
+
+    if actions.pressed(InputAction::MoveForward) {
+        direction += forward_xz;
+    }
+    if actions.pressed(InputAction::MoveBack) {
+        direction -= forward_xz;
+    }
+    if actions.pressed(InputAction::MoveLeft) {
+        direction -= right_xz;
+    }
+    if actions.pressed(InputAction::MoveRight) {
+        direction += right_xz;
+    }
+    direction += forward_xz * action_axes.movement.y + right_xz * action_axes.movement.x;
+
+    let walking = game_settings.game_mode == GameMode::Survival && game_settings.gravity_enabled;
+    let swimming = game_settings.game_mode != GameMode::Spectator && game_world.body_in_fluid(player_transform.translation);
+    if walking {
+        // Jump instead of flying up, in water jump swims up
+        if actions.pressed(InputAction::Jump) && swimming {
+            player.velocity.y = SWIM_UP_SPEED;
+        } else if actions.pressed(InputAction::Jump) && player.is_grounded {
+            player.velocity.y = PLAYER_JUMP_VELOCITY;
+        }
+    } else {
+        if actions.pressed(InputAction::Jump) {
+            direction.y += 1.0;
+        }
+        if actions.pressed(InputAction::Descend) {
+            direction.y -= 1.0;
+        }
+    }
+
+    if direction == Vec3::ZERO {
+        return;
+    }
+    // Keys always give full speed, a partly tilted stick walks slower
+    let speed = if swimming { game_settings.player_speed * WATER_SPEED_MULTIPLIER } else { game_settings.player_speed };
+    let movement = direction.clamp_length_max(1.0) * speed * time.delta_secs();
+
+    match game_settings.game_mode {
+        // Spectators fly straight through blocks
+        GameMode::Spectator => player_transform.translation += movement,
+        _ => {
+            // Swimmers can pull themselves out onto a ledge
+            walk_body(&game_world, &mut player_transform.translation, movement, (player.is_grounded || swimming) && walking);
+
+            let vertical = player_transform.translation + Vec3::Y * movement.y;
+            if movement.y != 0.0 && game_world.body_collision(vertical).is_none() {
+                player_transform.translation = vertical;
+            }
+        }
+    }
+}
+
+fn crim_ai(
+    player_query: Query<&Transform, (With<Player>, Without<Crim>)>,
+    mut crim_query: Query<(&mut Transform, &mut Crim)>,
+    time: Res<Time>,
+    mut game_world: ResMut<GameWorld>,
+    shelter: Res<Shelter>,
+    blocks_query: Query<(Entity, &Position), With<Block>>,
+    sheep_query: Query<(Entity, &Transform), (With<Sheep>, Without<Crim>)>,
+    mut noise_events: EventReader<NoiseEvent>,
+    time_of_day: Res<TimeOfDay>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    block_assets: Res<BlockAssets>,
+) {
+    let noises: Vec<&NoiseEvent> = noise_events.read().collect();
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation;
+    let vision_range = crim_vision_range(&time_of_day);
+    let speed_multiplier = crim_speed_multiplier(&time_of_day);
+
+    for (mut crim_transform, mut crim) in crim_query.iter_mut() {
+        let to_player = player_transform.translation - crim_transform.translation;
+        let distance = to_player.length();
+        
+        let crim_pos = crim_transform.translation;
+        let can_see_player = crim_can_see(&game_world, &crim_transform, player_pos, vision_range);
+
+        // Sheep make an easy meal when the player is nowhere to be seen
+        let prey = sheep_query.iter()
+            .map(|(entity, transform)| (entity, transform.translation))
+            .filter(|(_, pos)| crim_can_see(&game_world, &crim_transform, *pos, vision_range))
+            .min_by(|(_, a), (_, b)| a.distance(crim_pos).total_cmp(&b.distance(crim_pos)));
+
+        // Go and check out anything loud nearby
+        if let Some(noise) = noises.iter().find(|noise| noise.position.distance(crim_pos) < noise.radius) {
+            crim.heard_noise = Some(noise.position);
+            crim.last_known_player_pos = Some(noise.position);
+        }
+        
+        // Crim behavior logic
+        if can_see_player {
+            // Just spotted player
+            if !crim.spotted_player {
+                crim.spotted_player = true;
+                
+                // Emit particles when spotting player
+                let mut rng = rand::rng();
+                for _ in 0..5 {
+                    let random_dir = Vec3::new(
+                        rng.random::<f32>() * 2.0 - 1.0,
+                        rng.random::<f32>() * 2.0 - 1.0,
+                        rng.random::<f32>() * 2.0 - 1.0,
+                    ).normalize();
+                    
+                    commands.spawn((
+                        Mesh3d(meshes.add(Sphere::new(0.1))),
+                        MeshMaterial3d(materials.add(StandardMaterial {
+                            base_color: Color::srgb(1.0, 0.3, 0.0),
+                            emissive: Color::srgb(1.0, 0.3, 0.0).into(),
+                            ..default()
+                        })),
+                        Transform::from_translation(crim_pos + Vec3::new(0.0, 0.5, 0.0)),
+                        ParticleEffect {
+                            lifetime: 1.0,
+                            velocity: random_dir * 2.0,
+                            created: 0.0,
+                        },
+                    ));
+                }
+            }
+            
+            crim.state = CrimState::Chasing;
+            crim.chase_timer = 3.0; // Continue chasing for 3 seconds after losing sight
+            crim.last_known_player_pos = Some(player_pos);
+        } else if crim.chase_timer > 0.0 {
+            // Continue chasing for a bit even if player is out of sight
+            crim.chase_timer -= time.delta_secs();
+            
+            if crim.chase_timer <= 0.0 {
+                crim.spotted_player = false;
+            }
+        } else if shelter.sealed && shelter.weakest_wall.is_some() && distance < CRIM_BREAK_IN_RANGE {
+            // The player is holed up nearby, go through the weakest wall
+            crim.spotted_player = false;
+            crim.state = CrimState::BreakingIn;
+        } else if prey.is_some() {
+            crim.spotted_player = false;
+            crim.state = CrimState::Hunting;
+        } else if crim.last_known_player_pos.is_some() {
+            // Lost sight of player, check where they were last seen
+            crim.spotted_player = false;
+            crim.state = CrimState::Searching;
+        } else {
+            crim.spotted_player = false;
+            crim.state = CrimState::Idle;
+        }
+
+        let weakest_wall = shelter.weakest_wall.map(|(cell, _)| cell);
+        let (target, speed) = match crim.state {
+            CrimState::Chasing if can_see_player => (Some(player_pos), crim.speed),
+            CrimState::Chasing => (Some(player_pos), crim.speed * 0.7),
+            CrimState::Hunting => (prey.map(|(_, pos)| pos), crim.speed * 0.8),
+            CrimState::BreakingIn => (weakest_wall.map(cell_center), crim.speed * 0.7),
+            CrimState::Searching => (crim.last_known_player_pos, crim.speed * 0.5),
+            CrimState::Idle => (None, 0.0),
+        };
+        let swimming = game_world.body_in_fluid(crim_transform.translation);
+        let speed = speed * speed_multiplier * if swimming { CRIM_SWIM_SPEED_MULTIPLIER } else { 1.0 };
+        crim.target = target;
+
+        // Only turn to the player once it knows they are there, otherwise look where it is going
+        let look_at = if crim.state == CrimState::Chasing { Some(player_pos) } else { target };
+        if let Some(look_at) = look_at {
+            let direction = (look_at - crim_transform.translation).normalize_or_zero();
+            if direction != Vec3::ZERO {
+                let target_rotation = Quat::from_rotation_arc(Vec3::Z, direction);
+                crim_transform.rotation = crim_transform.rotation.slerp(target_rotation, time.delta_secs() * 2.0);
+            }
+        }
+
+        // Crim walks on the ground like the player
+        let mut velocity = crim.velocity;
+        crim.is_grounded = apply_gravity(&game_world, &mut crim_transform.translation, &mut velocity, time.delta_secs());
+        crim.velocity = velocity;
+
+        let mut blocked_by = None;
+        if let Some(target) = target {
+            let to_target = target - crim_transform.translation;
+            let walk_direction = Vec3::new(to_target.x, 0.0, to_target.z).normalize_or_zero();
+
+            // Crim shies away from torchlight and won't step anywhere brighter than where it stands
+            let here = game_world.light(LightChannel::Block, block_coords(crim_transform.translation));
+            let ahead = game_world.light(LightChannel::Block, block_coords(crim_transform.translation + walk_direction * CRIM_LIGHT_LOOKAHEAD));
+            if ahead < CRIM_LIGHT_AVERSION || ahead <= here {
+                blocked_by = walk_body(
+                    &game_world,
+                    &mut crim_transform.translation,
+                    walk_direction * speed * time.delta_secs(),
+                    crim.is_grounded || swimming,
+                );
+            }
+
+            // Paddle up when the way out of the water is blocked
+            if swimming && blocked_by.is_some() {
+                crim.velocity.y = SWIM_UP_SPEED;
+            }
+
+            // Nothing here, give up the search
+            if crim.state == CrimState::Searching && Vec3::new(to_target.x, 0.0, to_target.z).length() < 1.0 {
+                crim.last_known_player_pos = None;
+                crim.heard_noise = None;
+            }
+
+            // Caught a sheep
+            if let Some((prey, _)) = prey.filter(|_| crim.state == CrimState::Hunting && to_target.length() < 1.0) {
+                commands.entity(prey).despawn_recursive();
+            }
+
+            // Close enough to claw at the shelter wall directly
+            if crim.state == CrimState::BreakingIn && to_target.length() < CRIM_REACH {
+                blocked_by = weakest_wall;
+            }
+        }
+
+        // Smash through whatever is in the way, harder blocks take longer
+        let Some(cell) = blocked_by else {
+            crim.break_target = None;
+            crim.break_progress = 0.0;
+            continue;
+        };
+
+        if crim.break_target != Some(cell) {
+            crim.break_target = Some(cell);
+            crim.break_progress = 0.0;
+        }
+        crim.break_progress += time.delta_secs();
+
+        let Some(block_type) = game_world.block_type(cell) else {
+            continue;
+        };
+
+        // Plain doors just get pulled open, locked ones have to be smashed
+        if block_type.crim_can_open() && crim.break_progress >= CRIM_OPEN_DOOR_TIME {
+            game_world.set_open(cell, true);
+            crim.break_target = None;
+            crim.break_progress = 0.0;
+            continue;
+        }
+
+        if crim.break_progress >= CRIM_BREAK_DELAY + block_type.hardness() {
+            spill_container(&mut commands, &block_assets, &mut game_world, cell);
+
+            // Both halves of a smashed door come down together
+            let cells = game_world.door_cells(cell);
+            let cells = if cells.is_empty() { vec![cell] } else { cells };
+            for cell in &cells {
+                game_world.remove_block(*cell);
+            }
+
+            for (entity, position) in blocks_query.iter() {
+                if cells.contains(&(position.x, position.y, position.z)) {
+                    commands.entity(entity).despawn();
+                }
+            }
+
+            crim.break_target = None;
+            crim.break_progress = 0.0;
+        }
+    }
+}
+
+// Spawn new Crims around the player and remove ones that wandered too far off
+fn crim_spawner(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut spawner: ResMut<CrimSpawner>,
+    game_world: Res<GameWorld>,
+    game_settings: Res<GameSettings>,
+    time: Res<Time>,
+    player_query: Query<&Transform, With<Player>>,
+    camera_query: Query<&GlobalTransform, With<MainCamera>>,
+    crim_query: Query<(Entity, &Transform), With<Crim>>,
+    time_of_day: Res<TimeOfDay>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let player_pos = player_transform.translation;
+
+    let mut crim_count = 0;
+    for (entity, transform) in crim_query.iter() {
+        if transform.translation.distance(player_pos) > CRIM_DESPAWN_DISTANCE {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            crim_count += 1;
+        }
+    }
+
+    spawner.timer -= time.delta_secs();
+    if spawner.timer > 0.0 || crim_count >= game_settings.difficulty.max_crims() {
+        return;
+    }
+    spawner.timer = CRIM_SPAWN_INTERVAL;
+
+    let Ok(camera_transform) = camera_query.get_single() else {
+        return;
+    };
+    let eye_pos = camera_transform.translation();
+    let view_dir = camera_transform.forward();
+
+    let mut rng = rand::rng();
+    for _ in 0..CRIM_SPAWN_ATTEMPTS {
+        let angle = rng.random::<f32>() * std::f32::consts::TAU;
+        let radius = rng.random_range(CRIM_SPAWN_MIN_DISTANCE..CRIM_SPAWN_MAX_DISTANCE);
+        let x = (player_pos.x + angle.cos() * radius).floor() as i32;
+        let z = (player_pos.z + angle.sin() * radius).floor() as i32;
+
+        if !(-WORLD_SIZE..WORLD_SIZE).contains(&x) || !(-WORLD_SIZE..WORLD_SIZE).contains(&z) {
+            continue;
+        }
+
+        // Stand on the highest block of the column
+        let Some(ground) = game_world.blocks.keys()
+            .filter(|(bx, _, bz)| *bx == x && *bz == z)
+            .map(|(_, by, _)| *by)
+            .max()
+        else {
+            continue;
+        };
+
+        // Look for the first free cell with headroom under any overhang
+        let Some(feet) = (0..=ground + 1)
+            .map(|y| (x, y, z))
+            .find(|&(x, y, z)| {
+                game_world.is_solid((x, y - 1, z))
+                    && game_world.body_collision(Vec3::new(x as f32, y as f32, z as f32)).is_none()
+                    && game_world.is_dark((x, y, z), time_of_day.daylight())
+            })
+        else {
+            continue;
+        };
+
+        let spawn_pos = cell_center(feet);
+
+        // Never pop into existence where the player is looking
+        let to_spawn = spawn_pos - eye_pos;
+        let in_view = view_dir.dot(to_spawn.normalize_or_zero()) > CRIM_SPAWN_VIEW_COS
+            && raycast_blocks(&game_world, eye_pos, spawn_pos).is_none();
+        if in_view {
+            continue;
+        }
+
+        spawn_crim(
+            &mut commands,
+            &mut meshes,
+            &mut materials,
+            spawn_pos,
+            CRIM_SPEED * game_settings.difficulty.crim_speed_multiplier(),
+        );
+        break;
+    }
+}
+
+// Wander around, keep out of water and run away from Crim
+fn sheep_ai(
+    mut sheep_query: Query<(&mut Transform, &mut Sheep), Without<Crim>>,
+    crim_query: Query<&Transform, With<Crim>>,
+    game_world: Res<GameWorld>,
+    time: Res<Time>,
+) {
+    let mut rng = rand::rng();
+
+    for (mut transform, mut sheep) in sheep_query.iter_mut() {
+        let mut velocity = sheep.velocity;
+        sheep.is_grounded = apply_gravity(&game_world, &mut transform.translation, &mut velocity, time.delta_secs());
+        sheep.velocity = velocity;
+
+        let position = transform.translation;
+        let nearest_crim = crim_query.iter()
+            .map(|crim_transform| crim_transform.translation)
+            .filter(|crim_pos| crim_pos.distance(position) < SHEEP_FLEE_DISTANCE)
+            .min_by(|a, b| a.distance(position).total_cmp(&b.distance(position)));
+
+        let (direction, speed) = if let Some(crim_pos) = nearest_crim {
+            let away = position - crim_pos;
+            (Vec3::new(away.x, 0.0, away.z).normalize_or_zero(), SHEEP_FLEE_SPEED)
+        } else {
+            sheep.wander_timer -= time.delta_secs();
+            if sheep.wander_timer <= 0.0 {
+                sheep.wander_timer = rng.random_range(2.0..5.0);
+
+                // Graze in place about half of the time
+                sheep.wander_direction = if rng.random_bool(0.5) {
+                    let angle = rng.random::<f32>() * std::f32::consts::TAU;
+                    Vec3::new(angle.cos(), 0.0, angle.sin())
+                } else {
+                    Vec3::ZERO
+                };
+            }
+            (sheep.wander_direction, SHEEP_SPEED)
+        };
+
+        if direction == Vec3::ZERO {
+            continue;
+        }
+
+        // Turn around instead of walking into water
+        let (x, y, z) = block_coords(position + direction);
+        let wet = [(x, y, z), (x, y - 1, z)]
+            .iter()
+            .any(|cell| game_world.block_type(*cell) == Some(BlockType::Water));
+        if wet {
+            sheep.wander_direction = -direction;
+            continue;
+        }
+
+        let blocked = walk_body(
+            &game_world,
+            &mut transform.translation,
+            direction * speed * time.delta_secs(),
+            sheep.is_grounded,
+        );
+
+        // Bumped into a wall, pick a new direction
+        if blocked.is_some() {
+            sheep.wander_timer = 0.0;
+        }
+
+        transform.look_to(direction, Vec3::Y);
+    }
+}
+
+fn block_interaction(
+    actions: Res<ButtonInput<InputAction>>,
+    mut player_query: Query<(&Transform, &mut Player), Without<Sheep>>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut game_world: ResMut<GameWorld>,
+    blocks_query: Query<(Entity, &Position, &Block)>,
+    mut sheep_query: Query<(Entity, &Transform, &mut Sheep)>,
+    mut player_stats: ResMut<PlayerStats>,
+    mut noise_events: EventWriter<NoiseEvent>,
+    game_settings: Res<GameSettings>,
+    time: Res<Time>,
+    block_assets: Res<BlockAssets>,
+    mut run_score: ResMut<RunScore>,
+    camera_rig: Res<CameraRig>,
+) {
+    let (player_transform, mut player) = player_query.single_mut();
+    
+    if !player.has_pickaxe || game_settings.game_mode == GameMode::Spectator || camera_rig.mode == CameraMode::Free {
+        return;
+    }
+    
+    let camera_transform = camera_query.single();
+    
+    let creative = game_settings.game_mode == GameMode::Creative;
+    // Aim from the eyes so third person reaches the same blocks as first person
+    let camera_pos = player_transform.translation + EYE_OFFSET;
+    let camera_forward = player_transform.rotation * camera_transform.rotation * Dir3::NEG_Z;
+    let mut hit_something = false;
+
+    // Attacking
+    if actions.just_pressed(InputAction::Mine) {
+        // Add mining animation by spawning particles
+        commands.spawn((
+            Mesh3d(meshes.add(RegularPolygon::new(0.05, 3))),
+            MeshMaterial3d(materials.add(Color::srgb(1.0, 0.8, 0.0))),
+            Transform::from_translation(camera_pos + camera_forward * 1.5),
+            ParticleEffect { 
+                lifetime: 0.5,
+                velocity: camera_forward * 0.5, 
+                created: 0.0,
+            },
+        ));
+        
+        // Hitting a sheep takes priority over mining
+        let hit_sheep = sheep_query.iter_mut()
+            .filter(|(_, transform, _)| {
+                let to_sheep = transform.translation - camera_pos;
+                to_sheep.length() < SHEEP_HIT_REACH && camera_forward.dot(to_sheep.normalize_or_zero()) > 0.9
+            })
+            .min_by(|(_, a, _), (_, b, _)| {
+                a.translation.distance(camera_pos).total_cmp(&b.translation.distance(camera_pos))
+            });
+
+        if let Some((entity, transform, mut sheep)) = hit_sheep {
+            hit_something = true;
+            sheep.health = sheep.health.saturating_sub(1);
+            sheep.velocity.y = 4.0;
+
+            // Wool pops off like a mined block and has to be picked up
+            spawn_dropped_item(&mut commands, &block_assets, transform.translation, BlockType::Wool, 1, Vec3::Y * ITEM_POP_SPEED, 0.0);
+
+            if sheep.health == 0 {
+                commands.entity(entity).despawn_recursive();
+            }
+        }
+    }
+
+    // Mining blocks, creative breaks a block per click while survival has to keep digging
+    let mining = if creative {
+        actions.just_pressed(InputAction::Mine)
+    } else {
+        actions.pressed(InputAction::Mine)
+    };
+
+    if mining && !hit_something {
+        let max_reach = 5.0;
+        let mut closest_block = None;
+        let mut closest_distance = max_reach;
+        
+        for (entity, position, block) in blocks_query.iter() {
+            // Nothing to dig out of water
+            if block.block_type.is_fluid() {
+                continue;
+            }
+
+            let block_pos = Vec3::new(
+                position.x as f32 * BLOCK_SIZE,
+                position.y as f32 * BLOCK_SIZE,
+                position.z as f32 * BLOCK_SIZE,
+            );
+            
+            let distance = camera_pos.distance(block_pos);
+            
+            if distance < closest_distance {
+                let to_block = (block_pos - camera_pos).normalize();
+                
+                if camera_forward.dot(to_block) > 0.7 {
+                    closest_block = Some((entity, position.clone()));
+                    closest_distance = distance;
+                }
+            }
+        }
+        
+        if let Some((entity, position)) = closest_block {
+            // Get the block type
+            if let Ok((_, _, block)) = blocks_query.get(entity) {
+                let block_type = block.block_type;
+                let cell = (position.x, position.y, position.z);
+
+                if player.mining_target != Some(cell) {
+                    player.mining_target = Some(cell);
+                    player.mining_progress = 0.0;
+                }
+                player.mining_progress += time.delta_secs();
+
+                let mining_time = if creative { 0.0 } else { block_type.hardness() * PLAYER_MINING_TIME_SCALE };
+                if player.mining_progress >= mining_time {
+                    // A broken chest spills everything it held
+                    spill_container(&mut commands, &block_assets, &mut game_world, cell);
+
+                    // Remove the block from the world, both halves of a door come away together
+                    for other_half in game_world.door_cells(cell).into_iter().filter(|other| *other != cell) {
+                        game_world.remove_block(other_half);
+                        game_world.redraw.insert(other_half);
+                    }
+                    game_world.remove_block(cell);
+                    
+                    // Leave the block lying there to be picked up
+                    let drop = spawn_block_drop(&mut commands, &block_assets, cell, block_type, 1);
+                    commands.entity(drop).insert(Mined);
+
+                    run_score.blocks_mined += 1;
+                    
+                    commands.entity(entity).despawn();
+
+                    noise_events.send(NoiseEvent {
+                        position: cell_center(cell),
+                        radius: MINING_NOISE_RADIUS,
+                    });
+
+                    player.mining_target = None;
+                    player.mining_progress = 0.0;
+                }
+            }
+        } else {
+            player.mining_target = None;
+            player.mining_progress = 0.0;
+        }
+    } else {
+        player.mining_target = None;
+        player.mining_progress = 0.0;
+    }
+    
+    // Placing blocks
+    if actions.just_pressed(InputAction::Place) {
+        // Raycast to find where to place the block
+        let ray_start = camera_pos;
+        let _ray_end = camera_pos + camera_forward * 5.0;
+        
+        // Simple raycast implementation for block placement
+        let mut ray_pos = ray_start;
+        let ray_step = camera_forward * 0.1;
+        let mut block_to_place = None;
+        let mut last_empty_pos = None;
+        
+        for _ in 0..50 {  // 50 steps for max reach of 5.0
+            let block_x = (ray_pos.x / BLOCK_SIZE).floor() as i32;
+            let block_y = (ray_pos.y / BLOCK_SIZE).floor() as i32;
+            let block_z = (ray_pos.z / BLOCK_SIZE).floor() as i32;
+            
+            let block_pos = (block_x, block_y, block_z);
+            
+            // If we hit a block, we want to place in the last empty position
+            if game_world.is_solid(block_pos) {
+                block_to_place = last_empty_pos.map(|empty| (empty, block_pos));
+                break;
+            }
+            
+            last_empty_pos = Some(block_pos);
+            ray_pos += ray_step;
+        }
+        
+        if let Some((block_pos, against)) = block_to_place {
+            // Check if there's already a block at this position, open doors let the ray through but still take up their cell
+            if game_world.block_type(block_pos).is_none_or(|block_type| block_type.is_fluid()) {
+                // Check if player has this block type in inventory, creative has an endless supply
+                let has_block = creative || player_stats.inventory.get(&player.selected_block_type).copied().unwrap_or(0) > 0;
+                
+                if has_block {
+                    // Remove block from inventory
+                    if let Some(count) = player_stats.inventory.get_mut(&player.selected_block_type).filter(|_| !creative) {
+                        *count -= 1;
+                        if *count == 0 {
+                            player_stats.inventory.remove(&player.selected_block_type);
+                        }
+                    }
+                    
+                    // Placing into water pushes the water out
+                    if game_world.is_fluid(block_pos) {
+                        for (entity, position, _) in blocks_query.iter() {
+                            if (position.x, position.y, position.z) == block_pos {
+                                commands.entity(entity).despawn();
+                            }
+                        }
+                    }
+
+                    // Add a new block of the selected type, turned the way the player is facing
+                    let block_type = player.selected_block_type;
+                    let mut state = BlockState::from(block_type);
+                    if block_type.has_facing() {
+                        state = state.with_facing(Facing::from_direction(*camera_forward));
+                    }
+                    if block_type.has_axis() {
+                        state = state.with_axis(Axis::between(block_pos, against));
+                    }
+                    game_world.place_block(block_pos, state);
+                    
+                    spawn_block(&mut commands, &block_assets, block_pos, state);
+
+                    // Doors get a top half when there's room for one
+                    let above = (block_pos.0, block_pos.1 + 1, block_pos.2);
+                    if block_type.is_door() && !game_world.blocks.contains_key(&above) {
+                        let upper = state.with_upper(true);
+                        game_world.place_block(above, upper);
+                        spawn_block(&mut commands, &block_assets, above, upper);
+                    }
+
+                    noise_events.send(NoiseEvent {
+                        position: cell_center(block_pos),
+                        radius: PLACING_NOISE_RADIUS,
+                    });
+                }
+            }
+        }
+    }
+}
+
+// Open and close the door or trapdoor the player is looking at
+fn interact_system(
+    actions: Res<ButtonInput<InputAction>>,
+    player_query: Query<&Transform, With<Player>>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    mut game_world: ResMut<GameWorld>,
+    mut noise_events: EventWriter<NoiseEvent>,
+    game_settings: Res<GameSettings>,
+    camera_rig: Res<CameraRig>,
+    mut game_ui: ResMut<GameUI>,
+    mut windows: Query<&mut Window>,
+) {
+    if !actions.just_pressed(InputAction::Interact)
+        || game_settings.game_mode == GameMode::Spectator
+        || camera_rig.mode == CameraMode::Free
+    {
+        return;
+    }
+
+    // Using again closes the open chest
+    if game_ui.open_chest.take().is_some() {
+        set_cursor_grabbed(&mut windows, true);
+        return;
+    }
+    let (Ok(player_transform), Ok(camera_transform)) = (player_query.get_single(), camera_query.get_single()) else {
+        return;
+    };
+
+    // Walk the view ray to the first block, open doors included
+    let eye = player_transform.translation + EYE_OFFSET;
+    let forward = player_transform.rotation * camera_transform.rotation * Dir3::NEG_Z;
+    let Some(cell) = (0..(INTERACT_REACH / 0.1) as i32)
+        .map(|i| block_coords(eye + forward * (i as f32 * 0.1)))
+        .find(|cell| game_world.block_type(*cell).is_some_and(|block_type| !block_type.is_fluid()))
+        .filter(|cell| game_world.blocks[cell].block_type.is_openable() || game_world.blocks[cell].block_type.is_container())
+    else {
+        return;
+    };
+
+    if game_world.blocks[&cell].block_type.is_container() {
+        game_ui.open_chest = Some(cell);
+        set_cursor_grabbed(&mut windows, false);
+        return;
+    }
+
+    let open = !game_world.blocks[&cell].is_open();
+
+    // Don't shut a door on yourself
+    let (x, y, z) = block_coords(player_transform.translation);
+    let body: Vec<_> = (0..BODY_HEIGHT).map(|dy| (x, y + dy, z)).collect();
+    if !open && game_world.door_cells(cell).iter().any(|door| body.contains(door)) {
+        return;
+    }
+
+    game_world.set_open(cell, open);
+    noise_events.send(NoiseEvent {
+        position: cell_center(cell),
+        radius: DOOR_NOISE_RADIUS,
+    });
+}
+
+// Pending block work: neighbour updates this tick, scheduled ticks later on
+#[derive(Resource, Default)]
+struct BlockTicks {
+    timer: f32,
+    tick: u64,
+    neighbour_updates: VecDeque<(i32, i32, i32)>,
+    scheduled: BTreeMap<u64, Vec<(i32, i32, i32)>>,
+    scheduled_cells: HashSet<(i32, i32, i32)>,
+}
+
+impl BlockTicks {
+    // A cell changed, so it and its six neighbours get a look
+    fn notify_neighbours(&mut self, pos: (i32, i32, i32)) {
+        let (x, y, z) = pos;
+        self.neighbour_updates.extend([
+            pos,
+            (x + 1, y, z),
+            (x - 1, y, z),
+            (x, y + 1, z),
+            (x, y - 1, z),
+            (x, y, z + 1),
+            (x, y, z - 1),
+        ]);
+    }
+
+    // Tick `pos` after `delay` block ticks, unless it is already waiting
+    fn schedule(&mut self, pos: (i32, i32, i32), delay: u64) {
+        if self.scheduled_cells.insert(pos) {
+            self.scheduled.entry(self.tick + delay).or_default().push(pos);
+        }
+    }
+
+    // Scheduled cells that are due, at most `budget` of them, the rest wait for the next tick
+    fn take_due(&mut self, budget: usize) -> Vec<(i32, i32, i32)> {
+        let mut due = Vec::new();
+        while let Some(mut entry) = self.scheduled.first_entry() {
+            if *entry.key() > self.tick || due.len() >= budget {
+                break;
+            }
+            let cells = entry.get_mut();
+            let take = cells.len().min(budget - due.len());
+            due.extend(cells.drain(..take));
+            if cells.is_empty() {
+                entry.remove();
+            }
+        }
+        for pos in &due {
+            self.scheduled_cells.remove(pos);
+        }
+        due
+    }
+}
+
+// Change a cell from inside the block tick, telling its neighbours and remembering to redraw it
+fn tick_set_block(
+    game_world: &mut GameWorld,
+    block_ticks: &mut BlockTicks,
+    redraw: &mut HashSet<(i32, i32, i32)>,
+    pos: (i32, i32, i32),
+    block: Option<BlockState>,
+) {
+    match block {
+        Some(state) => {
+            game_world.blocks.insert(pos, state);
+        }
+        None => {
+            game_world.blocks.remove(&pos);
+        }
+    }
+    game_world.update_light(pos);
+    block_ticks.notify_neighbours(pos);
+    redraw.insert(pos);
+}
+
+// Something next door changed, decide whether this cell has work to do later
+fn on_neighbour_update(game_world: &GameWorld, block_ticks: &mut BlockTicks, pos: (i32, i32, i32)) {
+    let (x, y, z) = pos;
+    match game_world.block_type(pos) {
+        Some(block_type) if block_type.has_gravity() => {
+            if !game_world.is_supported(pos) {
+                block_ticks.schedule(pos, FALL_TICK_DELAY);
+            }
+        }
+        Some(block_type) if !block_type.is_fluid() => {}
+        // Water, or an empty cell that water next to it could flow into
+        _ => {
+            let wet = [pos, (x + 1, y, z), (x - 1, y, z), (x, y + 1, z), (x, y, z + 1), (x, y, z - 1)]
+                .into_iter()
+                .any(|cell| game_world.is_fluid(cell));
+            if wet {
+                block_ticks.schedule(pos, FLUID_TICK_DELAY);
+            }
+        }
+    }
+}
+
+// A cell's scheduled time has come
+fn on_scheduled_tick(
+    commands: &mut Commands,
+    block_assets: &BlockAssets,
+    game_world: &mut GameWorld,
+    block_ticks: &mut BlockTicks,
+    redraw: &mut HashSet<(i32, i32, i32)>,
+    pos: (i32, i32, i32),
+) {
+    let (x, y, z) = pos;
+    match game_world.block_type(pos) {
+        // Still nothing underneath, start falling
+        Some(block_type) if block_type.has_gravity() => {
+            if !game_world.is_supported(pos) {
+                tick_set_block(game_world, block_ticks, redraw, pos, None);
+                commands.spawn((
+                    Mesh3d(block_assets.cube_mesh.clone()),
+                    MeshMaterial3d(block_assets.materials[&block_type].clone()),
+                    Transform::from_xyz(x as f32 * BLOCK_SIZE, y as f32 * BLOCK_SIZE, z as f32 * BLOCK_SIZE),
+                    FallingBlock { block_type, velocity: 0.0 },
+                ));
+            }
+        }
+        // Water flows around blocks, never through them
+        Some(block_type) if !block_type.is_fluid() => {}
+        _ => {
+            let next = next_water_level(game_world, pos);
+            if next != game_world.water_level(pos) {
+                tick_set_block(game_world, block_ticks, redraw, pos, next.map(|level| BlockState::from(BlockType::Water).with_level(level)));
+            }
+        }
+    }
+}
+
+// A block picked at random gets a chance to grow or decay
+fn on_random_tick(
+    game_world: &mut GameWorld,
+    block_ticks: &mut BlockTicks,
+    redraw: &mut HashSet<(i32, i32, i32)>,
+    pos: (i32, i32, i32),
+    rng: &mut impl Rng,
+) {
+    let (x, y, z) = pos;
+    if game_world.block_type(pos) != Some(BlockType::Grass) || game_world.is_solid((x, y + 1, z)) {
+        return;
+    }
+
+    // Grass spreads onto nearby dirt with nothing on top of it
+    let target = (x + rng.random_range(-1..=1), y + rng.random_range(-1..=1), z + rng.random_range(-1..=1));
+    let (tx, ty, tz) = target;
+    if game_world.block_type(target) == Some(BlockType::Dirt) && !game_world.is_solid((tx, ty + 1, tz)) {
+        tick_set_block(game_world, block_ticks, redraw, target, Some(BlockType::Grass.into()));
+    }
+}
+
+// Run block ticks at a fixed rate: neighbour updates, then due scheduled ticks, then random ticks per chunk
+fn block_tick_system(
+    mut commands: Commands,
+    mut game_world: ResMut<GameWorld>,
+    mut block_ticks: ResMut<BlockTicks>,
+    blocks_query: Query<(Entity, &Position), With<Block>>,
+    block_assets: Res<BlockAssets>,
+    time: Res<Time>,
+) {
+    // Edits made anywhere else in the game since last frame
+    for pos in std::mem::take(&mut game_world.changed) {
+        block_ticks.notify_neighbours(pos);
+    }
+
+    // Don't try to catch up forever after a long frame
+    block_ticks.timer = (block_ticks.timer + time.delta_secs()).min(BLOCK_TICK_INTERVAL * MAX_BLOCK_TICKS_PER_FRAME as f32);
+
+    let game_world = game_world.as_mut();
+    let block_ticks = block_ticks.as_mut();
+    let mut redraw = std::mem::take(&mut game_world.redraw);
+    let mut rng = rand::rng();
+
+    while block_ticks.timer >= BLOCK_TICK_INTERVAL {
+        block_ticks.timer -= BLOCK_TICK_INTERVAL;
+        block_ticks.tick += 1;
+
+        for pos in std::mem::take(&mut block_ticks.neighbour_updates) {
+            on_neighbour_update(game_world, block_ticks, pos);
+        }
+
+        for pos in block_ticks.take_due(MAX_SCHEDULED_TICKS) {
+            on_scheduled_tick(&mut commands, &block_assets, game_world, block_ticks, &mut redraw, pos);
+        }
+
+        let chunks = (-WORLD_SIZE).div_euclid(CHUNK_SIZE)..=(WORLD_SIZE - 1).div_euclid(CHUNK_SIZE);
+        for chunk_x in chunks.clone() {
+            for chunk_z in chunks.clone() {
+                for _ in 0..RANDOM_TICKS_PER_CHUNK {
+                    let pos = (
+                        chunk_x * CHUNK_SIZE + rng.random_range(0..CHUNK_SIZE),
+                        rng.random_range(0..WORLD_HEIGHT * 2),
+                        chunk_z * CHUNK_SIZE + rng.random_range(0..CHUNK_SIZE),
+                    );
+                    on_random_tick(game_world, block_ticks, &mut redraw, pos, &mut rng);
+                }
+            }
+        }
+    }
+
+    if redraw.is_empty() {
+        return;
+    }
+
+    // Swap the entities of every cell that changed
+    for (entity, position) in blocks_query.iter() {
+        if redraw.contains(&(position.x, position.y, position.z)) {
+            commands.entity(entity).despawn();
+        }
+    }
+    for pos in redraw {
+        if let Some(state) = game_world.blocks.get(&pos) {
+            spawn_block(&mut commands, &block_assets, pos, *state);
+        }
+    }
+}
+
+// Whether a falling block at `block_pos` overlaps a body standing at `body_pos`
+fn falling_block_hits(block_pos: Vec3, body_pos: Vec3) -> bool {
+    let center = block_pos + Vec3::splat(0.5) * BLOCK_SIZE;
+    let horizontal = Vec2::new(center.x - body_pos.x, center.z - body_pos.z).length();
+    horizontal < 0.8 && center.y > body_pos.y && center.y < body_pos.y + BODY_HEIGHT as f32 * BLOCK_SIZE
+}
+
+// Drop falling blocks until they land, hurting whoever they fall on
+fn falling_block_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    mut game_world: ResMut<GameWorld>,
+    block_assets: Res<BlockAssets>,
+    mut falling_query: Query<(Entity, &mut Transform, &mut FallingBlock)>,
+    blocks_query: Query<(Entity, &Position), With<Block>>,
+    player_query: Query<&Transform, (With<Player>, Without<FallingBlock>)>,
+    mut crim_query: Query<(Entity, &Transform, &mut Crim), Without<FallingBlock>>,
+    mut player_stats: ResMut<PlayerStats>,
+    game_settings: Res<GameSettings>,
+) {
+    'falling: for (entity, mut transform, mut falling) in falling_query.iter_mut() {
+        falling.velocity += GRAVITY * time.delta_secs();
+        let start = transform.translation;
+        let drop = falling.velocity * time.delta_secs();
+
+        // Check every cell on the way down, so a long frame can't carry the block through a floor or a head
+        let steps = (drop.abs() / BLOCK_SIZE).ceil().max(1.0) as u32;
+        for step in 1..=steps {
+            let next = start + Vec3::Y * drop * step as f32 / steps as f32;
+
+            // Landing on someone breaks the block over their head
+            if let Ok(player_transform) = player_query.get_single() {
+                if game_settings.game_mode == GameMode::Survival && falling_block_hits(next, player_transform.translation) {
+                    player_stats.health = (player_stats.health - FALLING_BLOCK_DAMAGE).max(0.0);
+                    commands.entity(entity).despawn();
+                    continue 'falling;
+                }
+            }
+            if let Some((crim_entity, _, mut crim)) = crim_query.iter_mut()
+                .find(|(_, crim_transform, _)| falling_block_hits(next, crim_transform.translation))
+            {
+                crim.health -= FALLING_BLOCK_DAMAGE;
+                if crim.health <= 0.0 {
+                    commands.entity(crim_entity).despawn_recursive();
+                }
+                commands.entity(entity).despawn();
+                continue 'falling;
+            }
+
+            let cell = block_coords(next);
+            if game_world.is_solid(cell) {
+                // Settle in the cell above whatever stopped it
+                let landed = (cell.0, cell.1 + 1, cell.2);
+
+                // It crushes whatever it lands in, both halves of a door come down together
+                let crushed = game_world.door_cells(landed);
+                let crushed = if crushed.is_empty() { vec![landed] } else { crushed };
+                for cell in &crushed {
+                    game_world.remove_block(*cell);
+                }
+                for (block_entity, position) in blocks_query.iter() {
+                    if crushed.contains(&(position.x, position.y, position.z)) {
+                        commands.entity(block_entity).despawn();
+                    }
+                }
+                game_world.place_block(landed, falling.block_type);
+                spawn_block(&mut commands, &block_assets, landed, falling.block_type);
+                commands.entity(entity).despawn();
+                continue 'falling;
+            }
+            if next.y < -WORLD_HEIGHT as f32 * BLOCK_SIZE {
+                // Fell out of the world
+                commands.entity(entity).despawn();
+                continue 'falling;
+            }
+        }
+
+        transform.translation = start + Vec3::Y * drop;
+    }
+}
+
+// Move, bob, merge, age and pick up dropped items
+fn dropped_item_system(
+    mut commands: Commands,
+    time: Res<Time>,
+    game_world: Res<GameWorld>,
+    game_settings: Res<GameSettings>,
+    player_query: Query<&Transform, (With<Player>, Without<DroppedItem>)>,
+    mut items: Query<(Entity, &mut Transform, &mut DroppedItem, Has<Mined>)>,
+    mut player_stats: ResMut<PlayerStats>,
+    mut run_score: ResMut<RunScore>,
+) {
+    let delta = time.delta_secs();
+
+    for (entity, mut transform, mut item, _) in items.iter_mut() {
+        item.age += delta;
+        if item.age > ITEM_LIFETIME {
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        // Slide sideways until something gets in the way, then fall like everything else
+        let item = item.as_mut();
+        let sideways = Vec3::new(item.velocity.x, 0.0, item.velocity.z) * delta;
+        if game_world.is_solid(block_coords(item.position + sideways)) {
+            item.velocity.x = 0.0;
+            item.velocity.z = 0.0;
+        } else {
+            item.position += sideways;
+        }
+        let mut vertical = Vec3::Y * item.velocity.y;
+        let grounded = apply_gravity(&game_world, &mut item.position, &mut vertical, delta);
+        item.velocity.y = vertical.y;
+        if grounded {
+            let friction = (1.0 - ITEM_GROUND_FRICTION * delta).max(0.0);
+            item.velocity.x *= friction;
+            item.velocity.z *= friction;
+        }
+
+        let bob = (item.age * ITEM_BOB_SPEED).sin() * ITEM_BOB_HEIGHT;
+        transform.translation = item.position + Vec3::Y * (ITEM_HOVER + bob);
+        transform.rotation = Quat::from_rotation_y(item.age * ITEM_SPIN_SPEED);
+    }
+
+    // Stacks of the same block lying close together join up, as long as they were all mined or none were
+    let resting: Vec<(Entity, (BlockType, bool), Vec3)> = items.iter()
+        .map(|(entity, _, item, mined)| (entity, (item.block_type, mined), item.position))
+        .collect();
+    let mut merged = HashSet::new();
+    for (i, (keep, kind, position)) in resting.iter().enumerate() {
+        if merged.contains(keep) {
+            continue;
+        }
+        for (other, other_kind, other_position) in &resting[i + 1..] {
+            if other_kind != kind || merged.contains(other) || position.distance(*other_position) > ITEM_MERGE_RADIUS {
+                continue;
+            }
+            let Ok(count) = items.get(*other).map(|(_, _, item, _)| item.count) else {
+                continue;
+            };
+            if let Ok((_, _, mut item, _)) = items.get_mut(*keep) {
+                item.count += count;
+            }
+            merged.insert(*other);
+            commands.entity(*other).despawn();
+        }
+    }
+
+    // Spectators just drift past
+    if game_settings.game_mode == GameMode::Spectator {
+        return;
+    }
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    for (entity, _, item, mined) in items.iter() {
+        let in_reach = item.position.distance(player_transform.translation) < ITEM_PICKUP_RADIUS;
+        if in_reach && item.age >= item.pickup_delay && !merged.contains(&entity) {
+            *player_stats.inventory.entry(item.block_type).or_insert(0) += item.count;
+
+            // Only ore dug out of the ground counts as collected, not ore dropped and picked back up
+            if mined && item.block_type == BlockType::Ore {
+                run_score.ore_collected += item.count;
+            }
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Throw one of the selected block out in front of the player
+fn drop_item_system(
+    mut commands: Commands,
+    actions: Res<ButtonInput<InputAction>>,
+    player_query: Query<(&Transform, &Player)>,
+    camera_query: Query<&Transform, With<MainCamera>>,
+    mut player_stats: ResMut<PlayerStats>,
+    block_assets: Res<BlockAssets>,
+    game_settings: Res<GameSettings>,
+    camera_rig: Res<CameraRig>,
+) {
+    if !actions.just_pressed(InputAction::Drop)
+        || game_settings.game_mode == GameMode::Spectator
+        || camera_rig.mode == CameraMode::Free
+    {
+        return;
+    }
+    let (Ok((player_transform, player)), Ok(camera_transform)) = (player_query.get_single(), camera_query.get_single()) else {
+        return;
+    };
+
+    // Creative has an endless supply, like placing
+    let block_type = player.selected_block_type;
+    if game_settings.game_mode != GameMode::Creative {
+        let Some(count) = player_stats.inventory.get_mut(&block_type).filter(|count| **count > 0) else {
+            return;
+        };
+        *count -= 1;
+        if *count == 0 {
+            player_stats.inventory.remove(&block_type);
+        }
+    }
+
+    let forward = player_transform.rotation * camera_transform.rotation * Dir3::NEG_Z;
+    let eye = player_transform.translation + EYE_OFFSET;
+    let velocity = forward * ITEM_THROW_SPEED + Vec3::Y * ITEM_POP_SPEED;
+    spawn_dropped_item(&mut commands, &block_assets, eye + forward * 0.5, block_type, 1, velocity, ITEM_THROW_PICKUP_DELAY);
+}
+
+// What the water in `pos` should become, None for dry
+fn next_water_level(game_world: &GameWorld, pos: (i32, i32, i32)) -> Option<u8> {
+    let (x, y, z) = pos;
+    let current = game_world.water_level(pos);
+
+    // Sources never drain and water never pours into the void under the world
+    if current == Some(0) {
+        return current;
+    }
+    if y < 0 {
+        return None;
+    }
+
+    // Anything under water falls straight down
+    if game_world.water_level((x, y + 1, z)).is_some() {
+        return Some(1);
+    }
+
+    let mut sources = 0;
+    let mut level = None;
+    for neighbour in [(x + 1, y, z), (x - 1, y, z), (x, y, z + 1), (x, y, z - 1)] {
+        let Some(neighbour_level) = game_world.water_level(neighbour) else {
+            continue;
+        };
+        if neighbour_level == 0 {
+            sources += 1;
+        }
+
+        // Water only spreads sideways once it is resting on something
+        let (nx, ny, nz) = neighbour;
+        if neighbour_level < FLUID_MAX_LEVEL && game_world.blocks.contains_key(&(nx, ny - 1, nz)) {
+            level = Some(level.map_or(neighbour_level + 1, |level: u8| level.min(neighbour_level + 1)));
+        }
+    }
+
+    // A gap between two sources fills in as a new source
+    if sources >= 2 && game_world.blocks.contains_key(&(x, y - 1, z)) {
+        return Some(0);
+    }
+
+    level
+}
+
+// Unit cube shaded for a light level, `end_shade` darkens the faces pointing up and down
+fn lit_cube(level: u8, end_shade: f32) -> Mesh {
+    let brightness = LIGHT_FALLOFF.powi((MAX_LIGHT - level) as i32);
+    let mesh = Mesh::from(Cuboid::default());
+    let colors: Vec<[f32; 4]> = match mesh.attribute(Mesh::ATTRIBUTE_NORMAL) {
+        Some(VertexAttributeValues::Float32x3(normals)) => normals.iter()
+            .map(|normal| {
+                let shade = if normal[1].abs() > 0.5 { brightness * end_shade } else { brightness };
+                [shade, shade, shade, 1.0]
+            })
+            .collect(),
+        _ => vec![[brightness, brightness, brightness, 1.0]; mesh.count_vertices()],
+    };
+    mesh.with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+}
+
+// Give new blocks and blocks next to a lighting change the cube baked for their light level
+fn block_light_system(
+    mut game_world: ResMut<GameWorld>,
+    block_assets: Res<BlockAssets>,
+    mut blocks_query: Query<(Ref<Block>, &Position, &mut Mesh3d)>,
+) {
+    let light_changed = std::mem::take(&mut game_world.light_changed);
+
+    for (block, position, mut mesh) in blocks_query.iter_mut() {
+        let (x, y, z) = (position.x, position.y, position.z);
+        let near_change = [(x, y, z), (x + 1, y, z), (x - 1, y, z), (x, y + 1, z), (x, y - 1, z), (x, y, z + 1), (x, y, z - 1)]
+            .iter()
+            .any(|cell| light_changed.contains(cell));
+        // Shaped blocks keep their own mesh, the ones there are glow anyway
+        if (!block.is_added() && !near_change) || block.block_type.shape().is_some() {
+            continue;
+        }
+
+        let lit_meshes = if block.block_type.has_axis() { &block_assets.lit_log_meshes } else { &block_assets.lit_meshes };
+        let lit_mesh = &lit_meshes[game_world.block_brightness((x, y, z)) as usize];
+        if mesh.0 != *lit_mesh {
+            mesh.0 = lit_mesh.clone();
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+enum CameraMode {
+    FirstPerson,
+    ThirdPerson,
+    Free,
+}
+
+impl CameraMode {
+    fn next(self) -> CameraMode {
+        match self {
+            CameraMode::FirstPerson => CameraMode::ThirdPerson,
+            CameraMode::ThirdPerson => CameraMode::Free,
+            CameraMode::Free => CameraMode::FirstPerson,
+        }
+    }
+}
+
+// Where the camera sits, the free camera keeps its own world position
+#[derive(Resource)]
+struct CameraRig {
+    mode: CameraMode,
+    free_position: Vec3,
+}
+
+impl Default for CameraRig {
+    fn default() -> Self {
+        Self {
+            mode: CameraMode::FirstPerson,
+            free_position: Vec3::ZERO,
+        }
+    }
+}
+
+// How far the camera can pull back along `direction` before it would clip into a block
+fn camera_boom_length(game_world: &GameWorld, from: Vec3, direction: Vec3, max_length: f32) -> f32 {
+    let step = 0.1;
+    let mut length = 0.0;
+    while length < max_length {
+        let next = (length + step).min(max_length);
+        if game_world.is_solid(block_coords(from + direction * next)) {
+            return (length - CAMERA_COLLISION_MARGIN).max(0.0);
+        }
+        length = next;
+    }
+    max_length
+}
+
+// Place the camera for the current mode, runs after the player has moved
+fn camera_rig_system(
+    actions: Res<ButtonInput<InputAction>>,
+    action_axes: Res<ActionAxes>,
+    mut camera_rig: ResMut<CameraRig>,
+    mut camera_query: Query<(&mut Transform, &GlobalTransform), With<MainCamera>>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<MainCamera>)>,
+    mut arm_query: Query<(&mut Transform, &mut Visibility), (With<PlayerArm>, Without<MainCamera>, Without<Player>)>,
+    game_world: Res<GameWorld>,
+    time: Res<Time>,
+) {
+    let Ok((mut camera_transform, camera_global)) = camera_query.get_single_mut() else {
+        return;
+    };
+    let Ok(mut player_transform) = player_query.get_single_mut() else {
+        return;
+    };
+
+    if actions.just_pressed(InputAction::CycleCamera) {
+        camera_rig.mode = camera_rig.mode.next();
+        // Detach from wherever the camera was looking from
+        camera_rig.free_position = camera_global.translation();
+
+        // Coming back from the free camera, turn the body to face where it was looking
+        if camera_rig.mode != CameraMode::Free {
+            let (yaw, pitch, _) = camera_transform.rotation.to_euler(EulerRot::YXZ);
+            player_transform.rotate_y(yaw);
+            camera_transform.rotation = Quat::from_rotation_x(pitch);
+        }
+    }
+
+    let look = player_transform.rotation * camera_transform.rotation;
+    let eye = player_transform.translation + EYE_OFFSET;
+
+    let world_position = match camera_rig.mode {
+        CameraMode::FirstPerson => eye,
+        CameraMode::ThirdPerson => {
+            let boom = look * THIRD_PERSON_OFFSET;
+            let length = camera_boom_length(&game_world, eye, boom.normalize(), boom.length());
+            eye + boom.normalize() * length
+        }
+        CameraMode::Free => {
+            // Fly where the camera points, ignoring blocks
+            let mut direction = look * Vec3::new(action_axes.movement.x, 0.0, -action_axes.movement.y);
+            if actions.pressed(InputAction::MoveForward) {
+                direction += look * Vec3::NEG_Z;
+            }
+            if actions.pressed(InputAction::MoveBack) {
+                direction += look * Vec3::Z;
+            }
+            if actions.pressed(InputAction::MoveLeft) {
+                direction += look * Vec3::NEG_X;
+            }
+            if actions.pressed(InputAction::MoveRight) {
+                direction += look * Vec3::X;
+            }
+            if actions.pressed(InputAction::Jump) {
+                direction.y += 1.0;
+            }
+            if actions.pressed(InputAction::Descend) {
+                direction.y -= 1.0;
+            }
+            camera_rig.free_position += direction.clamp_length_max(1.0) * FREE_CAMERA_SPEED * time.delta_secs();
+            camera_rig.free_position
+        }
+    };
+
+    // The camera is parented to the player, so store it relative to them
+    camera_transform.translation = player_transform.rotation.inverse() * (world_position - player_transform.translation);
+
+    // The arm hangs off the eyes and tilts with the camera pitch, the body already gives it yaw
+    let (_, pitch, _) = camera_transform.rotation.to_euler(EulerRot::YXZ);
+    let pitch_rotation = Quat::from_rotation_x(pitch);
+    let arm_visibility = if camera_rig.mode == CameraMode::FirstPerson {
+        Visibility::Inherited
+    } else {
+        Visibility::Hidden
+    };
+    for (mut arm_transform, mut visibility) in arm_query.iter_mut() {
+        arm_transform.translation = EYE_OFFSET + pitch_rotation * ARM_OFFSET;
+        arm_transform.rotation = pitch_rotation * Quat::from_rotation_x(-0.3);
+        *visibility = arm_visibility;
+    }
+}
+
+fn camera_control(
+    mut windows: Query<&mut Window>,
+    mut mouse_motion_events: EventReader<MouseMotion>,
+    mut query: Query<&mut Transform, With<MainCamera>>,
+    mut player_query: Query<&mut Transform, (With<Player>, Without<MainCamera>)>,
+    game_settings: Res<GameSettings>,
+    action_axes: Res<ActionAxes>,
+    camera_rig: Res<CameraRig>,
+    time: Res<Time>,
+) {
+    let mut rotation_move = Vec2::ZERO;
+    
+    // Get mouse delta
+    for event in mouse_motion_events.read() {
+        rotation_move += event.delta * game_settings.mouse_sensitivity;
+    }
+
+    // Stick look turns at a steady rate, pushing up looks up
+    let look = action_axes.look * game_settings.gamepad_look_speed * time.delta_secs();
+    rotation_move += Vec2::new(look.x, -look.y);
+    
+    if rotation_move.length_squared() > 0.0 {
+        let mut transform = query.single_mut();
+        let mut player_transform = player_query.single_mut();
+        
+        // Apply mouse delta as camera rotation (pitch and yaw)
+        let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
+        
+        // Increment yaw and pitch based on mouse movement
+        let invert = if game_settings.invert_y { -1.0 } else { 1.0 };
+        yaw -= rotation_move.x;
+        pitch -= rotation_move.y * invert;
+        
+        // Clamp pitch to avoid camera flipping
+        pitch = pitch.clamp(-1.5, 1.5);
+        
+        // Yaw turns the body, except the free camera looks around on its own.
+        // Leaving free camera folds its heading back into the body.
+        if camera_rig.mode != CameraMode::Free {
+            player_transform.rotate_y(yaw);
+            yaw = 0.0;
+        }
+        transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
+        
+        // Grab cursor for continuous rotation
+        let mut window = windows.single_mut();
+        window.cursor_options.grab_mode = bevy::window::CursorGrabMode::Locked;
+        window.cursor_options.visible = false;
+    }
+}
+
+// Apply gravity and handle collisions
+fn physics_system(
+    time: Res<Time>,
+    game_world: Res<GameWorld>,
+    mut player_query: Query<(&mut Transform, &mut Player)>,
+    game_settings: Res<GameSettings>,
+) {
+    if !game_settings.gravity_enabled || game_settings.game_mode != GameMode::Survival {
+        return;
+    }
+
+    let (mut transform, mut player) = player_query.single_mut();
+    let mut velocity = player.velocity;
+
+    player.is_grounded = apply_gravity(&game_world, &mut transform.translation, &mut velocity, time.delta_secs());
+    player.velocity = velocity;
+}
+
+// Hold your breath underwater, drown when it runs out
+fn breath_system(
+    time: Res<Time>,
+    game_world: Res<GameWorld>,
+    player_query: Query<&Transform, With<Player>>,
+    mut player_stats: ResMut<PlayerStats>,
+    game_settings: Res<GameSettings>,
+) {
+    let Ok(transform) = player_query.get_single() else {
+        return;
+    };
+
+    let underwater = game_settings.game_mode == GameMode::Survival && game_world.head_in_fluid(transform.translation);
+    if underwater {
+        player_stats.breath = (player_stats.breath - time.delta_secs()).max(0.0);
+        if player_stats.breath <= 0.0 {
+            player_stats.health = (player_stats.health - DROWNING_DAMAGE * time.delta_secs()).max(0.0);
+        }
+    } else if player_stats.breath < MAX_BREATH {
+        player_stats.breath = (player_stats.breath + BREATH_RECOVERY_RATE * time.delta_secs()).min(MAX_BREATH);
+    }
+}
+
+// Switch between block types
+fn block_selection_system(
+    actions: Res<ButtonInput<InputAction>>,
+    mut player_query: Query<&mut Player>,
+) {
+    let mut player = player_query.single_mut();
+    
+    // Hotbar slots select different block types
+    for (index, block_type) in HOTBAR.into_iter().enumerate() {
+        if actions.just_pressed(InputAction::SelectSlot(index as u8 + 1)) {
+            player.selected_block_type = block_type;
+        }
+    }
+
+    // Cycle through the hotbar, wrapping at either end
+    let step = if actions.just_pressed(InputAction::NextSlot) {
+        1
+    } else if actions.just_pressed(InputAction::PreviousSlot) {
+        HOTBAR.len() - 1
+    } else {
+        return;
+    };
+    let current = HOTBAR.iter().position(|block_type| *block_type == player.selected_block_type).unwrap_or(0);
+    player.selected_block_type = HOTBAR[(current + step) % HOTBAR.len()];
+}
+
+// Display UI
+fn ui_system(
+    _commands: Commands,
+    game_ui: Res<GameUI>,
+    player_query: Query<&Player>,
+    crim_query: Query<(), With<Crim>>,
+    mut contexts: EguiContexts,
+    player_stats: Res<PlayerStats>,
+    game_settings: Res<GameSettings>,
+    game_world: Res<GameWorld>,
+    shelter: Res<Shelter>,
+    time_of_day: Res<TimeOfDay>,
+    run_score: Res<RunScore>,
+    camera_rig: Res<CameraRig>,
+) {
+    // HUD with the essentials, always visible
+    egui::Area::new(egui::Id::new("hud"))
+        .anchor(egui::Align2::LEFT_BOTTOM, egui::vec2(10.0, -10.0))
+        .show(contexts.ctx_mut(), |ui| {
+            let (hours, minutes) = time_of_day.clock();
+            ui.label(format!("Day {} {:02}:{:02} - {:?}", time_of_day.day + 1, hours, minutes, game_settings.game_mode));
+            if camera_rig.mode != CameraMode::FirstPerson {
+                ui.label(format!("Camera: {:?}", camera_rig.mode));
+            }
+            if game_settings.game_mode == GameMode::Survival {
+                ui.label(format!("Health: {:.0}/{:.0}", player_stats.health, player_stats.max_health));
+                if player_stats.breath < MAX_BREATH {
+                    ui.add(egui::ProgressBar::new(player_stats.breath / MAX_BREATH).desired_width(150.0).text("Breath"));
+                }
+            }
+
+            let objective_progress = match game_settings.objective {
+                Objective::SurviveNights(nights) => format!("{}/{}", run_score.nights_survived(&time_of_day), nights),
+                Objective::CollectOre(ore) => format!("{}/{}", run_score.ore_collected, ore),
+                Objective::ReachBeacon => "follow the light".to_string(),
+            };
+            ui.label(format!("{} ({}) - Score: {}", game_settings.objective.describe(), objective_progress, run_score.total()));
+
+            // Mining progress on the block being dug
+            let mining = player_query.get_single().ok().and_then(|player| {
+                let block_type = game_world.block_type(player.mining_target?)?;
+                Some(player.mining_progress / (block_type.hardness() * PLAYER_MINING_TIME_SCALE))
+            });
+            if let Some(progress) = mining {
+                ui.add(egui::ProgressBar::new(progress.min(1.0)).desired_width(150.0).text("Mining"));
+            }
+
+            if shelter.sealed {
+                let weakest = shelter.weakest_wall
+                    .map(|(_, block_type)| format!("{:?}", block_type))
+                    .unwrap_or_else(|| "none".to_string());
+                ui.colored_label(
+                    egui::Color32::LIGHT_GREEN,
+                    format!("Sheltered ({} blocks of air, weakest wall: {})", shelter.volume, weakest),
+                );
+            } else {
+                ui.colored_label(egui::Color32::LIGHT_RED, "Exposed");
+            }
+        });
+
+    if game_ui.show_debug {
+        let player = player_query.single();
+        let ctx = contexts.ctx_mut();
+        
+        egui::Window::new("Debug Info").show(ctx, |ui| {
+            ui.label(format!("Selected Block: {:?}", player.selected_block_type));
+            ui.label(format!("Is Grounded: {}", player.is_grounded));
+            ui.label(format!("Velocity: {:?}", player.velocity));
+            ui.separator();
+            ui.label(format!("Health: {}/{}", player_stats.health, player_stats.max_health));
+            ui.label(format!("Inventory Items: {}", player_stats.inventory.len()));
+            ui.separator();
+            ui.label(format!("Render Distance: {}", game_settings.render_distance));
+            ui.label(format!("Gravity Enabled: {}", game_settings.gravity_enabled));
+            ui.label(format!("Difficulty: {:?}", game_settings.difficulty));
+            ui.label(format!("Crims: {}/{}", crim_query.iter().count(), game_settings.difficulty.max_crims()));
+            ui.separator();
+            ui.label(format!("Total Blocks: {}", game_world.blocks.len()));
+            
+            // Display blocks in inventory
+            if !player_stats.inventory.is_empty() {
+                ui.collapsing("Inventory", |ui| {
+                    for (block_type, count) in &player_stats.inventory {
+                        ui.label(format!("{:?}: {}", block_type, count));
+                    }
+                });
+            }
+        });
+    }
+    
+    if game_ui.show_crosshair {
+        // Add crosshair in the center of the screen using Bevy UI
+        let ctx = contexts.ctx_mut();
+        
+        // Simple crosshair in the center
+        let screen_size = ctx.screen_rect().size();
+        let center = egui::pos2(screen_size.x / 2.0, screen_size.y / 2.0);
+        
+        ctx.debug_painter().circle_filled(
+            center, 
+            2.0, 
+            egui::Color32::WHITE
+        );
+    }
+}
+
+// Draw what Crim is thinking, toggled with F3
+fn ai_debug_system(
+    actions: Res<ButtonInput<InputAction>>,
+    mut game_ui: ResMut<GameUI>,
+    mut gizmos: Gizmos,
+    mut contexts: EguiContexts,
+    camera_query: Query<(&Camera, &GlobalTransform), With<MainCamera>>,
+    player_query: Query<&Transform, With<Player>>,
+    crim_query: Query<(&Transform, &Crim)>,
+    game_world: Res<GameWorld>,
+    time_of_day: Res<TimeOfDay>,
+) {
+    if actions.just_pressed(InputAction::ToggleAiDebug) {
+        game_ui.show_ai_debug = !game_ui.show_ai_debug;
+    }
+
+    if !game_ui.show_ai_debug {
+        return;
+    }
+
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+    let Ok((camera, camera_transform)) = camera_query.get_single() else {
+        return;
+    };
+    let ctx = contexts.ctx_mut();
+    let painter = ctx.debug_painter();
+
+    for (transform, crim) in crim_query.iter() {
+        let crim_pos = transform.translation;
+        let player_pos = player_transform.translation;
+
+        // Line of sight: green when clear, red up to the block in the way
+        match raycast_blocks(&game_world, crim_pos, player_pos) {
+            None => {
+                gizmos.line(crim_pos, player_pos, Color::srgb(0.0, 1.0, 0.0));
+            }
+            Some(cell) => {
+                gizmos.line(crim_pos, cell_center(cell), Color::srgb(1.0, 0.0, 0.0));
+                gizmos.cuboid(
+                    Transform::from_translation(cell_center(cell)).with_scale(Vec3::splat(BLOCK_SIZE * 1.02)),
+                    Color::srgb(1.0, 0.0, 0.0),
+                );
+            }
+        }
+
+        // Vision cone flattened onto the ground plane, as far as Crim can see the player where they stand
+        let range = crim_sight_range(&game_world, player_pos, crim_vision_range(&time_of_day));
+        let facing = crim_facing(transform);
+        let facing = Vec3::new(facing.x, 0.0, facing.z).normalize_or_zero();
+        let half_angle = CRIM_VISION_COS.acos();
+        let cone: Vec<Vec3> = (0..=16)
+            .map(|i| {
+                let angle = -half_angle + 2.0 * half_angle * i as f32 / 16.0;
+                crim_pos + Quat::from_rotation_y(angle) * facing * range
+            })
+            .collect();
+        gizmos.line(crim_pos, cone[0], Color::srgb(1.0, 1.0, 1.0));
+        gizmos.line(crim_pos, cone[cone.len() - 1], Color::srgb(1.0, 1.0, 1.0));
+        gizmos.linestrip(cone, Color::srgb(1.0, 1.0, 1.0));
+
+        // Where Crim is heading and what it is trying to break. There is no pathfinding,
+        // Crim walks straight at its target, so this line is its whole route.
+        if let Some(target) = crim.target {
+            gizmos.line(crim_pos, target, Color::srgb(0.0, 0.6, 1.0));
+            gizmos.sphere(Isometry3d::from_translation(target), 0.25, Color::srgb(0.0, 0.6, 1.0));
+        }
+        if let Some(cell) = crim.break_target {
+            gizmos.cuboid(
+                Transform::from_translation(cell_center(cell)).with_scale(Vec3::splat(BLOCK_SIZE * 1.05)),
+                Color::srgb(1.0, 0.5, 0.0),
+            );
+        }
+
+        if let Some(noise) = crim.heard_noise {
+            gizmos.sphere(Isometry3d::from_translation(noise), 0.5, Color::srgb(1.0, 1.0, 0.0));
+        }
+
+        // State label above its head
+        if let Ok(label_pos) = camera.world_to_viewport(camera_transform, crim_pos + Vec3::Y * 1.5) {
+            painter.text(
+                egui::pos2(label_pos.x, label_pos.y),
+                egui::Align2::CENTER_BOTTOM,
+                format!("{:?}", crim.state),
+                egui::FontId::proportional(16.0),
+                egui::Color32::YELLOW,
+            );
+        }
+    }
+}
+
+// System to animate and despawn particles
+fn particle_system(
+    mut commands: Commands,
+    mut particles: Query<(Entity, &mut Transform, &mut ParticleEffect)>,
+    time: Res<Time>,
+) {
+    for (entity, mut transform, mut particle) in particles.iter_mut() {
+        // Update age
+        if particle.created == 0.0 {
+            particle.created = time.elapsed_secs();
+        }
+        
+        let age = time.elapsed_secs() - particle.created;
+        
+        // Apply velocity
+        transform.translation += particle.velocity * time.delta_secs();
+        
+        // Add some gravity effect to particles
+        particle.velocity.y -= 2.0 * time.delta_secs();
+        
+        // Remove particle if it's too old
+        if age > particle.lifetime {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+// Crim hurts the player while it is close enough to grab them
+fn crim_attack(
+    player_query: Query<&Transform, With<Player>>,
+    mut crim_query: Query<(&Transform, &mut Crim)>,
+    mut player_stats: ResMut<PlayerStats>,
+    mut run_score: ResMut<RunScore>,
+    time: Res<Time>,
+) {
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    for (crim_transform, mut crim) in crim_query.iter_mut() {
+        let distance = crim_transform.translation.distance(player_transform.translation);
+        if distance < CRIM_ATTACK_RANGE {
+            player_stats.health = (player_stats.health - CRIM_ATTACK_DAMAGE * time.delta_secs()).max(0.0);
+        }
+
+        // Getting away from a Crim that was right on top of you is worth points
+        if distance < CLOSE_CALL_DISTANCE {
+            crim.near_player = true;
+        } else if crim.near_player && distance > CLOSE_CALL_ESCAPE_DISTANCE {
+            crim.near_player = false;
+            run_score.close_calls += 1;
+        }
+    }
+}
+
+fn check_game_over(
+    player_stats: Res<PlayerStats>,
+    mut run_score: ResMut<RunScore>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    if player_stats.health <= 0.0 {
+        run_score.outcome = RunOutcome::Died;
+        next_state.set(GameState::GameOver);
+    }
+}
+
+// Track time survived and end the run once the objective is met
+fn update_run(
+    time: Res<Time>,
+    time_of_day: Res<TimeOfDay>,
+    game_settings: Res<GameSettings>,
+    player_query: Query<&Transform, With<Player>>,
+    beacon_query: Query<&Transform, With<Beacon>>,
+    mut run_score: ResMut<RunScore>,
+    mut next_state: ResMut<NextState<GameState>>,
+) {
+    run_score.time_survived += time.delta_secs();
+
+    let complete = match game_settings.objective {
+        Objective::SurviveNights(nights) => run_score.nights_survived(&time_of_day) >= nights,
+        Objective::CollectOre(ore) => run_score.ore_collected >= ore,
+        Objective::ReachBeacon => {
+            let Ok(player_transform) = player_query.get_single() else {
+                return;
+            };
+            beacon_query.iter().any(|beacon| {
+                let offset = beacon.translation - player_transform.translation;
+                Vec2::new(offset.x, offset.z).length() < BEACON_REACH
+            })
+        }
+    };
+
+    if complete {
+        run_score.outcome = RunOutcome::Won;
+        next_state.set(GameState::GameOver);
+    }
+}
+
+// Remember if the run ever leaves Survival, switching back doesn't make it count again
+fn track_run_mode(game_settings: Res<GameSettings>, mut run_score: ResMut<RunScore>) {
+    if game_settings.game_mode != GameMode::Survival {
+        run_score.left_survival = true;
+    }
+}
+
+fn record_high_score(
+    run_score: Res<RunScore>,
+    game_settings: Res<GameSettings>,
+    mut high_scores: ResMut<HighScores>,
+) {
+    if !run_score.counts() {
+        high_scores.last_rank = None;
+        return;
+    }
+
+    let result = if run_score.outcome == RunOutcome::Won { "won" } else { "died" };
+    let description = format!("{} - {}", game_settings.objective.describe(), result);
+
+    high_scores.last_rank = high_scores.record(run_score.total(), description);
+    if let Err(err) = high_scores.save() {
+        println!("Failed to save high scores: {}", err);
+    }
+}
+
+fn crafting_open(game_ui: Res<GameUI>) -> bool {
+    game_ui.show_crafting
+}
+
+fn chest_open(game_ui: Res<GameUI>) -> bool {
+    game_ui.open_chest.is_some()
+}
+
+// Lock the mouse for looking around, or free it for clicking in a window
+fn set_cursor_grabbed(windows: &mut Query<&mut Window>, grabbed: bool) {
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor_options.grab_mode = if grabbed {
+            bevy::window::CursorGrabMode::Locked
+        } else {
+            bevy::window::CursorGrabMode::None
+        };
+        window.cursor_options.visible = !grabbed;
+    }
+}
+
+fn crafting_toggle(
+    actions: Res<ButtonInput<InputAction>>,
+    mut game_ui: ResMut<GameUI>,
+    mut windows: Query<&mut Window>,
+) {
+    if !actions.just_pressed(InputAction::OpenCrafting) {
+        return;
+    }
+
+    game_ui.show_crafting = !game_ui.show_crafting;
+    set_cursor_grabbed(&mut windows, !game_ui.show_crafting);
+}
+
+// Turn blocks from the inventory into torches and lanterns
+fn crafting_ui(
+    mut contexts: EguiContexts,
+    mut game_ui: ResMut<GameUI>,
+    mut player_stats: ResMut<PlayerStats>,
+    mut windows: Query<&mut Window>,
+) {
+    let mut close = false;
+
+    egui::Window::new("Crafting")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::Grid::new("recipes").num_columns(3).show(ui, |ui| {
+                for recipe in &RECIPES {
+                    ui.label(format!("{} {:?}", recipe.count, recipe.output));
+
+                    let ingredients: Vec<String> = recipe.ingredients.iter()
+                        .map(|(block_type, needed)| {
+                            let have = player_stats.inventory.get(block_type).copied().unwrap_or(0);
+                            format!("{} {:?} ({})", needed, block_type, have)
+                        })
+                        .collect();
+                    ui.label(ingredients.join(" + "));
+
+                    let can_craft = recipe.can_craft(&player_stats.inventory);
+                    if ui.add_enabled(can_craft, egui::Button::new("Craft")).clicked() {
+                        recipe.craft(&mut player_stats.inventory);
+                    }
+                    ui.end_row();
+                }
+            });
+
+            ui.separator();
+            if ui.button("Close").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                close = true;
+            }
+        });
+
+    if close {
+        game_ui.show_crafting = false;
+        set_cursor_grabbed(&mut windows, true);
+    }
+}
+
+// One side of the chest window, clicking moves blocks over to the other side
+fn transfer_column(ui: &mut egui::Ui, title: &str, from: &mut HashMap<BlockType, u32>, to: &mut HashMap<BlockType, u32>) {
+    ui.heading(title);
+    if from.is_empty() {
+        ui.weak("Empty");
+    }
+
+    let mut block_types: Vec<BlockType> = from.keys().copied().collect();
+    block_types.sort_by_key(|block_type| format!("{:?}", block_type));
+    for block_type in block_types {
+        let count = from[&block_type];
+        ui.horizontal(|ui| {
+            ui.label(format!("{:?}: {}", block_type, count));
+            if ui.small_button("1").clicked() {
+                move_items(from, to, block_type, 1);
+            }
+            if ui.small_button("All").clicked() {
+                move_items(from, to, block_type, count);
+            }
+        });
+    }
+}
+
+// Move blocks between the player's inventory and the open chest
+fn chest_ui(
+    mut contexts: EguiContexts,
+    mut game_ui: ResMut<GameUI>,
+    mut game_world: ResMut<GameWorld>,
+    mut player_stats: ResMut<PlayerStats>,
+    mut windows: Query<&mut Window>,
+) {
+    let Some(cell) = game_ui.open_chest else {
+        return;
+    };
+    // Crim got to it first
+    if game_world.block_type(cell) != Some(BlockType::Chest) {
+        game_ui.open_chest = None;
+        set_cursor_grabbed(&mut windows, true);
+        return;
+    }
+
+    let container = game_world.containers.entry(cell).or_default();
+    let inventory = &mut player_stats.inventory;
+    let mut close = false;
+
+    egui::Window::new("Chest")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            ui.columns(2, |columns| {
+                transfer_column(&mut columns[0], "Inventory", inventory, &mut container.items);
+                transfer_column(&mut columns[1], "Chest", &mut container.items, inventory);
+            });
+
+            ui.separator();
+            if ui.button("Close").clicked() || ui.input(|i| i.key_pressed(egui::Key::Escape)) {
+                close = true;
+            }
+        });
+
+    if container.items.is_empty() {
+        game_world.containers.remove(&cell);
+    }
+    if close {
+        game_ui.open_chest = None;
+        set_cursor_grabbed(&mut windows, true);
+    }
+}
+
+fn pause_toggle(
+    actions: Res<ButtonInput<InputAction>>,
+    state: Res<State<GameState>>,
+    mut next_state: ResMut<NextState<GameState>>,
+    game_ui: Res<GameUI>,
+) {
+    // The settings window uses the keyboard for rebinding
+    if !actions.just_pressed(InputAction::Pause) || game_ui.show_settings {
+        return;
+    }
+
+    match state.get() {
+        GameState::Playing => next_state.set(GameState::Paused),
+        GameState::Paused => next_state.set(GameState::Playing),
+        _ => {}
+    }
+}
+
+fn grab_cursor(mut windows: Query<&mut Window>) {
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor_options.grab_mode = bevy::window::CursorGrabMode::Locked;
+        window.cursor_options.visible = false;
+    }
+}
+
+fn release_cursor(mut windows: Query<&mut Window>) {
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor_options.grab_mode = bevy::window::CursorGrabMode::None;
+        window.cursor_options.visible = true;
+    }
+}
+
+// The settings window only draws on the menus, so leaving one shuts it rather than leaving it open unseen
+fn close_settings(mut game_ui: ResMut<GameUI>) {
+    game_ui.show_settings = false;
+    game_ui.rebinding = None;
+}
+
+// Tear down the previous run so a fresh world can be generated
+fn cleanup_world(
+    mut commands: Commands,
+    entities: Query<Entity, Or<(With<Block>, With<Player>, With<Crim>, With<Sheep>, With<ParticleEffect>, With<Beacon>, With<FallingBlock>, With<DroppedItem>)>>,
+    mut game_world: ResMut<GameWorld>,
+    mut player_stats: ResMut<PlayerStats>,
+    mut spawner: ResMut<CrimSpawner>,
+    mut shelter: ResMut<Shelter>,
+    mut camera_rig: ResMut<CameraRig>,
+    mut crim_debug: ResMut<CrimDebug>,
+    mut block_ticks: ResMut<BlockTicks>,
+    mut game_ui: ResMut<GameUI>,
+) {
+    for entity in entities.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    game_ui.show_crafting = false;
+    game_ui.open_chest = None;
+
+    game_world.blocks.clear();
+    game_world.changed.clear();
+    game_world.sky_light.clear();
+    game_world.block_light.clear();
+    game_world.light_changed.clear();
+    game_world.redraw.clear();
+    game_world.containers.clear();
+    *block_ticks = BlockTicks::default();
+    player_stats.health = player_stats.max_health;
+    player_stats.breath = MAX_BREATH;
+    player_stats.inventory.clear();
+    spawner.timer = CRIM_SPAWN_INTERVAL;
+    *shelter = Shelter::default();
+    *camera_rig = CameraRig::default();
+    *crim_debug = CrimDebug::default();
+}
+
+fn finish_loading(mut next_state: ResMut<NextState<GameState>>) {
+    next_state.set(GameState::Playing);
+}
+
+fn game_mode_picker(ui: &mut egui::Ui, game_mode: &mut GameMode) {
+    egui::ComboBox::from_label("Game Mode")
+        .selected_text(format!("{:?}", game_mode))
+        .show_ui(ui, |ui| {
+            for mode in GameMode::ALL {
+                ui.selectable_value(game_mode, mode, format!("{:?}", mode));
+            }
+        });
+}
+
+fn main_menu_ui(
+    mut contexts: EguiContexts,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut exit: EventWriter<AppExit>,
+    mut game_settings: ResMut<GameSettings>,
+    mut world_save: ResMut<WorldSave>,
+    mut game_ui: ResMut<GameUI>,
+) {
+    egui::CentralPanel::default().show(contexts.ctx_mut(), |ui| {
+        ui.vertical_centered(|ui| {
+            ui.add_space(ui.available_height() * 0.3);
+            ui.heading(egui::RichText::new("CrimCraft").size(48.0));
+            ui.label("Mine, build, and hide from Crim");
+            ui.add_space(24.0);
+
+            game_mode_picker(ui, &mut game_settings.game_mode);
+            egui::ComboBox::from_label("Difficulty")
+                .selected_text(format!("{:?}", game_settings.difficulty))
+                .show_ui(ui, |ui| {
+                    for difficulty in [Difficulty::Easy, Difficulty::Normal, Difficulty::Hard] {
+                        ui.selectable_value(&mut game_settings.difficulty, difficulty, format!("{:?}", difficulty));
+                    }
+                });
+            egui::ComboBox::from_label("Objective")
+                .selected_text(game_settings.objective.describe())
+                .show_ui(ui, |ui| {
+                    for objective in Objective::CHOICES {
+                        ui.selectable_value(&mut game_settings.objective, objective, objective.describe());
+                    }
+                });
+            ui.add_space(12.0);
+
+            if save_path().exists() && ui.button("Continue").clicked() {
+                world_save.load_requested = true;
+                next_state.set(GameState::Loading);
+            }
+            if ui.button("New World").clicked() {
+                next_state.set(GameState::Loading);
+            }
+            if ui.button("Settings").clicked() {
+                game_ui.show_settings = true;
+            }
+            if ui.button("Quit").clicked() {
+                exit.send(AppExit::Success);
+            }
+        });
+    });
+}
+
+fn loading_ui(mut contexts: EguiContexts) {
+    egui::CentralPanel::default().show(contexts.ctx_mut(), |ui| {
+        ui.centered_and_justified(|ui| {
+            ui.heading("Generating world...");
+        });
+    });
+}
+
+fn pause_menu_ui(
+    mut contexts: EguiContexts,
+    mut next_state: ResMut<NextState<GameState>>,
+    mut exit: EventWriter<AppExit>,
+    mut game_settings: ResMut<GameSettings>,
+    mut save_events: EventWriter<SaveGameEvent>,
+    mut game_ui: ResMut<GameUI>,
+) {
+    egui::Window::new("Paused")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            game_mode_picker(ui, &mut game_settings.game_mode);
+            ui.separator();
+
+            if ui.button("Resume").clicked() {
+                next_state.set(GameState::Playing);
+            }
+            if ui.button("Settings").clicked() {
+                game_ui.show_settings = true;
+            }
+            if ui.button("Save Game").clicked() {
+                save_events.send(SaveGameEvent);
+            }
+            if ui.button("Save and Quit to Menu").clicked() {
+                save_events.send(SaveGameEvent);
+                next_state.set(GameState::MainMenu);
+            }
+            if ui.button("Quit").clicked() {
+                exit.send(AppExit::Success);
+            }
+        });
+}
+
+fn game_over_ui(
+    mut contexts: EguiContexts,
+    mut next_state: ResMut<NextState<GameState>>,
+    run_score: Res<RunScore>,
+    high_scores: Res<HighScores>,
+) {
+    let title = if run_score.outcome == RunOutcome::Won { "You Made It" } else { "Game Over" };
+
+    egui::Window::new(title)
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            if run_score.outcome == RunOutcome::Won {
+                ui.label("Objective complete!");
+            } else {
+                ui.label("Crim got you.");
+            }
+            ui.add_space(8.0);
+
+            // Run summary
+            let seconds = run_score.time_survived as u32;
+            ui.label(format!("Time survived: {}:{:02}", seconds / 60, seconds % 60));
+            ui.label(format!("Blocks mined: {}", run_score.blocks_mined));
+            ui.label(format!("Close calls: {}", run_score.close_calls));
+            ui.heading(format!("Score: {}", run_score.total()));
+            if run_score.cheated {
+                ui.weak("Console commands were used, so this run isn't recorded");
+            } else if run_score.left_survival {
+                ui.weak("Part of this run wasn't played in Survival, so it isn't recorded");
+            } else if let Some(rank) = high_scores.last_rank {
+                ui.colored_label(egui::Color32::GOLD, format!("New high score! #{}", rank + 1));
+            }
+
+            ui.separator();
+            ui.label("High Scores");
+            for (i, (score, description)) in high_scores.entries.iter().enumerate() {
+                ui.label(format!("{}. {} ({})", i + 1, score, description));
+            }
+            ui.add_space(8.0);
+
+            if ui.button("Try Again").clicked() {
+                next_state.set(GameState::Loading);
+            }
+            if ui.button("Main Menu").clicked() {
+                next_state.set(GameState::MainMenu);
+            }
+        });
+}
+
+// Setup block assets, the sun and ambient lighting
+fn setup_environment(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+) {
+    // One shared mesh and material per block type
+    commands.insert_resource(BlockAssets {
+        cube_mesh: meshes.add(Cuboid::default()),
+        lit_meshes: (0..=MAX_LIGHT)
+            .map(|level| meshes.add(lit_cube(level, 1.0)))
+            .collect(),
+        lit_log_meshes: (0..=MAX_LIGHT)
+            .map(|level| meshes.add(lit_cube(level, LOG_END_SHADE)))
+            .collect(),
+        shaped_meshes: BlockType::ALL
+            .into_iter()
+            .filter_map(|block_type| {
+                let size = block_type.shape()?;
+                let mesh = Mesh::from(Cuboid::from_size(size)).translated_by(Vec3::Y * (size.y - 1.0) / 2.0);
+                Some((block_type, meshes.add(mesh)))
+            })
+            .collect(),
+        materials: BlockType::ALL
+            .into_iter()
+            .map(|block_type| (block_type, materials.add(block_type.material())))
+            .collect(),
+        flowing_water: materials.add(StandardMaterial {
+            base_color: Color::srgba(0.3, 0.5, 0.9, 0.7),
+            alpha_mode: AlphaMode::Blend,
+            perceptual_roughness: 0.1,
+            ..default()
+        }),
+    });
+
+    // Add ambient light
+    commands.insert_resource(AmbientLight {
+        color: Color::srgb(DAY_AMBIENT_COLOR[0], DAY_AMBIENT_COLOR[1], DAY_AMBIENT_COLOR[2]),
+        brightness: DAY_AMBIENT_BRIGHTNESS,
+    });
+    
+    // Add a distant directional light for sun effect, moved by the day/night cycle
+    commands.spawn((
+        DirectionalLight {
+            color: Color::srgb(1.0, 0.95, 0.8),
+            illuminance: SUN_ILLUMINANCE,
+            shadows_enabled: true,
+            ..default()
+        },
+        Transform::from_xyz(10.0, 50.0, 10.0)
+            .looking_at(Vec3::ZERO, Vec3::Y),
+        Sun,
+    ));
+}
+
+fn lerp_color(from: [f32; 3], to: [f32; 3], t: f32) -> Color {
+    Color::srgb(
+        from[0] + (to[0] - from[0]) * t,
+        from[1] + (to[1] - from[1]) * t,
+        from[2] + (to[2] - from[2]) * t,
+    )
+}
+
+// Move the sun across the sky and fade the lighting between day and night
+fn day_night_cycle(
+    time: Res<Time>,
+    game_settings: Res<GameSettings>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut sun_query: Query<(&mut Transform, &mut DirectionalLight), With<Sun>>,
+    mut ambient_light: ResMut<AmbientLight>,
+    mut clear_color: ResMut<ClearColor>,
+) {
+    time_of_day.time += time.delta_secs() / game_settings.day_length;
+    if time_of_day.time >= 1.0 {
+        time_of_day.time -= 1.0;
+        time_of_day.day += 1;
+    }
+
+    let daylight = time_of_day.daylight();
+    let angle = (time_of_day.time - 0.25) * std::f32::consts::TAU;
+
+    for (mut transform, mut light) in sun_query.iter_mut() {
+        let sun_direction = Vec3::new(angle.cos(), angle.sin(), 0.3).normalize();
+        *transform = Transform::from_translation(sun_direction * 50.0).looking_at(Vec3::ZERO, Vec3::Y);
+
+        // Warm near the horizon, white at noon
+        let height = time_of_day.sun_elevation().clamp(0.0, 1.0);
+        light.color = Color::srgb(1.0, 0.6 + 0.35 * height, 0.4 + 0.4 * height);
+        light.illuminance = SUN_ILLUMINANCE * daylight;
+    }
+
+    ambient_light.color = lerp_color(NIGHT_AMBIENT_COLOR, DAY_AMBIENT_COLOR, daylight);
+    ambient_light.brightness = NIGHT_AMBIENT_BRIGHTNESS + (DAY_AMBIENT_BRIGHTNESS - NIGHT_AMBIENT_BRIGHTNESS) * daylight;
+    clear_color.0 = lerp_color(NIGHT_SKY_COLOR, DAY_SKY_COLOR, daylight);
+}
+
+// Where the save file lives
+fn save_path() -> PathBuf {
+    data_dir().join("world.sav")
+}
+
+// Per-user directory for saves and other game data
+fn data_dir() -> PathBuf {
+    std::env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local").join("share")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("crimcraft")
+}
+
+// Per-user directory for settings
+fn config_dir() -> PathBuf {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("crimcraft")
+}
+
+// Push changed settings to the window, camera and audio
+fn apply_settings(
+    game_settings: Res<GameSettings>,
+    mut windows: Query<&mut Window>,
+    mut projections: Query<&mut Projection, With<MainCamera>>,
+    mut global_volume: ResMut<GlobalVolume>,
+) {
+    if !game_settings.is_changed() {
+        return;
+    }
+
+    if let Ok(mut window) = windows.get_single_mut() {
+        let present_mode = game_settings.present_mode();
+        let mode = game_settings.window_mode.window_mode();
+        if window.present_mode != present_mode {
+            window.present_mode = present_mode;
+        }
+        if window.mode != mode {
+            window.mode = mode;
+        }
+        let windowed = game_settings.window_mode == WindowModeSetting::Windowed;
+        if windowed
+            && (window.resolution.width() != game_settings.window_width
+                || window.resolution.height() != game_settings.window_height)
+        {
+            window.resolution.set(game_settings.window_width, game_settings.window_height);
+        }
+    }
+
+    for mut projection in projections.iter_mut() {
+        if let Projection::Perspective(perspective) = projection.as_mut() {
+            *perspective = PerspectiveProjection {
+                aspect_ratio: perspective.aspect_ratio,
+                ..game_settings.projection()
+            };
+        }
+    }
+
+    *global_volume = GlobalVolume::new(game_settings.master_volume);
+}
+
+fn settings_ui(
+    mut contexts: EguiContexts,
+    mut game_ui: ResMut<GameUI>,
+    mut game_settings: ResMut<GameSettings>,
+    mut input_bindings: ResMut<InputBindings>,
+    keyboard_input: Res<ButtonInput<KeyCode>>,
+    mouse_button: Res<ButtonInput<MouseButton>>,
+) {
+    if !game_ui.show_settings {
+        return;
+    }
+
+    // Waiting for a key or button to bind
+    if let Some(action) = game_ui.rebinding {
+        let pressed = keyboard_input
+            .get_just_pressed()
+            .find(|key| BINDABLE_KEYS.contains(key))
+            .map(|key| Binding::Key(*key))
+            .or_else(|| {
+                mouse_button
+                    .get_just_pressed()
+                    .find(|button| BINDABLE_MOUSE_BUTTONS.contains(button))
+                    .map(|button| Binding::Mouse(*button))
+            });
+        if let Some(binding) = pressed {
+            input_bindings.bindings.insert(action, binding);
+            game_ui.rebinding = None;
+        }
+    }
+
+    egui::Window::new("Settings")
+        .anchor(egui::Align2::CENTER_CENTER, egui::vec2(0.0, 0.0))
+        .collapsible(false)
+        .resizable(false)
+        .show(contexts.ctx_mut(), |ui| {
+            // Only take a mutable borrow when something actually changes
+            let mut settings = *game_settings;
+
+            ui.add(egui::Slider::new(&mut settings.mouse_sensitivity, GameSettings::MOUSE_SENSITIVITY_RANGE).text("Mouse sensitivity"));
+            ui.checkbox(&mut settings.invert_y, "Invert Y");
+            ui.add(egui::Slider::new(&mut settings.fov, GameSettings::FOV_RANGE).text("Field of view"));
+            ui.add(egui::Slider::new(&mut settings.render_distance, GameSettings::RENDER_DISTANCE_RANGE).text("Render distance"));
+            ui.add(egui::Slider::new(&mut settings.player_speed, GameSettings::PLAYER_SPEED_RANGE).text("Walk speed"));
+            ui.checkbox(&mut settings.vsync, "VSync");
+            egui::ComboBox::from_label("Window mode")
+                .selected_text(format!("{:?}", settings.window_mode))
+                .show_ui(ui, |ui| {
+                    for mode in WindowModeSetting::ALL {
+                        ui.selectable_value(&mut settings.window_mode, mode, format!("{:?}", mode));
+                    }
+                });
+            ui.add(egui::Slider::new(&mut settings.window_width, GameSettings::WINDOW_WIDTH_RANGE).step_by(10.0).text("Window width"));
+            ui.add(egui::Slider::new(&mut settings.window_height, GameSettings::WINDOW_HEIGHT_RANGE).step_by(10.0).text("Window height"));
+            ui.add(egui::Slider::new(&mut settings.master_volume, GameSettings::MASTER_VOLUME_RANGE).text("Volume"));
+            ui.add(egui::Slider::new(&mut settings.day_length, GameSettings::DAY_LENGTH_RANGE).text("Day length (s)"));
+            ui.add(egui::Slider::new(&mut settings.gamepad_deadzone, GameSettings::GAMEPAD_DEADZONE_RANGE).text("Stick deadzone"));
+            ui.add(egui::Slider::new(&mut settings.gamepad_look_speed, GameSettings::GAMEPAD_LOOK_SPEED_RANGE).text("Stick look speed"));
+
+            if settings != *game_settings {
+                *game_settings = settings;
+            }
+
+            ui.collapsing("Controls", |ui| {
+                let conflicts = input_bindings.conflicts();
+                egui::ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    egui::Grid::new("bindings").striped(true).show(ui, |ui| {
+                        for action in InputAction::ALL {
+                            let conflicting = conflicts.iter().any(|(a, b)| *a == action || *b == action);
+                            let label = egui::RichText::new(action.label());
+                            ui.label(if conflicting { label.color(egui::Color32::RED) } else { label });
+
+                            let text = if game_ui.rebinding == Some(action) {
+                                "Press a key...".to_string()
+                            } else {
+                                input_bindings
+                                    .bindings
+                                    .get(&action)
+                                    .map_or("Unbound".to_string(), |binding| binding.label())
+                            };
+                            if ui.button(text).clicked() {
+                                game_ui.rebinding = Some(action);
+                            }
+                            ui.end_row();
+                        }
+                    });
+                });
+
+                for (first, second) in &conflicts {
+                    ui.colored_label(
+                        egui::Color32::RED,
+                        format!("{} and {} share the same binding", first.label(), second.label()),
+                    );
+                }
+
+                if ui.button("Reset to defaults").clicked() {
+                    *input_bindings = InputBindings::default();
+                    game_ui.rebinding = None;
+                }
+            });
+
+            ui.separator();
+            let conflicts = !input_bindings.conflicts().is_empty();
+            if ui.add_enabled(!conflicts, egui::Button::new("Done")).clicked() {
+                game_ui.show_settings = false;
+                game_ui.rebinding = None;
+                if let Err(err) = game_settings.save() {
+                    println!("Failed to save settings: {}", err);
+                }
+                if let Err(err) = input_bindings.save() {
+                    println!("Failed to save key bindings: {}", err);
+                }
+            }
+        });
 }
 
-fn player_movement(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut player_query: Query<&mut Transform, (With<Player>, Without<MainCamera>)>,
-    camera_query: Query<&Transform, With<MainCamera>>,
-    time: Res<Time>,
+// Write the world when the pause menu asks for it
+fn save_game(
+    mut save_events: EventReader<SaveGameEvent>,
+    game_world: Res<GameWorld>,
+    time_of_day: Res<TimeOfDay>,
+    player_stats: Res<PlayerStats>,
+    run_score: Res<RunScore>,
+    game_settings: Res<GameSettings>,
+    player_query: Query<&Transform, With<Player>>,
+    item_query: Query<(&DroppedItem, Has<Mined>)>,
+    beacon_query: Query<&Beacon>,
 ) {
-    let mut player_transform = player_query.single_mut();
-    let camera_transform = camera_query.single();
-    
-    let mut direction = Vec3::ZERO;
-    let forward = camera_transform.forward();
-    let right = camera_transform.right();
-    
-    // Get forward/backward movement in the XZ plane
-    let forward_xz = Vec3::new(forward.x, 0.0, forward.z).normalize_or_zero();
-    let right_xz = Vec3::new(right.x, 0.0, right.z).normalize_or_zero();
+    if save_events.read().count() == 0 {
+        return;
+    }
 
-    if keyboard_input.pressed(KeyCode::KeyW) {
-        direction += forward_xz;
+    let Ok(player_transform) = player_query.get_single() else {
+        return;
+    };
+
+    let save = SaveData {
+        seed: game_world.seed,
+        time_of_day: time_of_day.time,
+        day: time_of_day.day,
+        player_position: player_transform.translation,
+        health: player_stats.health,
+        breath: player_stats.breath,
+        game_mode: game_settings.game_mode,
+        objective: game_settings.objective,
+        beacon: beacon_query.get_single().ok().map(|beacon| beacon.cell),
+        inventory: player_stats.inventory.clone(),
+        blocks: game_world.blocks.clone(),
+        containers: game_world.containers.clone(),
+        dropped_items: item_query.iter().map(|(item, mined)| (item.position, item.block_type, item.count, mined)).collect(),
+        run_score: run_score.clone(),
+    };
+
+    match save.write(&save_path()) {
+        Ok(()) => println!("Saved world to {}", save_path().display()),
+        Err(err) => println!("Failed to save world: {}", err),
     }
-    if keyboard_input.pressed(KeyCode::KeyS) {
-        direction -= forward_xz;
+}
+
+// A command the console knows about, `complete` suggests values for an argument index
+struct ConsoleCommand {
+    name: &'static str,
+    usage: &'static str,
+    complete: fn(usize) -> Vec<String>,
+}
+
+fn no_completions(_: usize) -> Vec<String> {
+    Vec::new()
+}
+
+fn block_names() -> Vec<String> {
+    BlockType::ALL.iter().map(|block_type| format!("{:?}", block_type).to_lowercase()).collect()
+}
+
+// Swap the partly typed last word of `line` for `word`, keeping the slash in front of a command
+fn complete_last_word(line: &str, word: &str) -> String {
+    let start = line.rfind(' ').map_or(0, |space| space + 1);
+    let start = if line[start..].starts_with('/') { start + 1 } else { start };
+    format!("{}{}", &line[..start], word)
+}
+
+// Commands other systems have registered, each handles its own ConsoleCommandEvent
+#[derive(Resource, Default)]
+struct CommandRegistry {
+    commands: Vec<ConsoleCommand>,
+}
+
+impl CommandRegistry {
+    fn register(&mut self, name: &'static str, usage: &'static str, complete: fn(usize) -> Vec<String>) {
+        self.commands.push(ConsoleCommand { name, usage, complete });
     }
-    if keyboard_input.pressed(KeyCode::KeyA) {
-        direction -= right_xz;
+
+    fn find(&self, name: &str) -> Option<&ConsoleCommand> {
+        self.commands.iter().find(|command| command.name == name)
     }
-    if keyboard_input.pressed(KeyCode::KeyD) {
-        direction += right_xz;
+
+    // Candidates for the last word of a partly typed line
+    fn completions(&self, line: &str) -> Vec<String> {
+        let line = line.trim_start().trim_start_matches('/');
+        let mut words: Vec<&str> = line.split_whitespace().collect();
+        if line.is_empty() || line.ends_with(' ') {
+            words.push("");
+        }
+
+        let (last, done) = words.split_last().unwrap_or((&"", &[]));
+        let candidates = match done.split_first() {
+            None => self.commands.iter().map(|command| command.name.to_string()).collect(),
+            Some((name, args)) => self.find(name).map_or(Vec::new(), |command| (command.complete)(args.len())),
+        };
+
+        candidates.into_iter().filter(|candidate| candidate.starts_with(&last.to_lowercase())).collect()
     }
-    if keyboard_input.pressed(KeyCode::Space) {
-        direction.y += 1.0;
+}
+
+#[derive(Event)]
+struct ConsoleCommandEvent {
+    name: String,
+    args: Vec<String>,
+}
+
+#[derive(Resource, Default)]
+struct ConsoleState {
+    open: bool,
+    input: String,
+    history: Vec<String>,
+    history_index: Option<usize>,
+    log: Vec<(String, bool)>, // Line and whether it is an error
+}
+
+impl ConsoleState {
+    fn print(&mut self, line: impl Into<String>) {
+        self.log.push((line.into(), false));
     }
-    if keyboard_input.pressed(KeyCode::ShiftLeft) {
-        direction.y -= 1.0;
+
+    fn error(&mut self, line: impl Into<String>) {
+        self.log.push((line.into(), true));
     }
 
-    if direction != Vec3::ZERO {
-        direction = direction.normalize();
-        player_transform.translation += direction * PLAYER_SPEED * time.delta_secs();
+    fn reply(&mut self, result: Result<String, String>) {
+        match result {
+            Ok(line) => self.print(line),
+            Err(line) => self.error(line),
+        }
     }
 }
 
-fn crim_ai(
-    player_query: Query<&Transform, (With<Player>, Without<Crim>)>,
-    mut crim_query: Query<(&mut Transform, &mut Crim)>,
-    time: Res<Time>,
-    game_world: Res<GameWorld>,
-    mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-) {
-    let player_transform = player_query.single();
-    let (mut crim_transform, mut crim) = crim_query.single_mut();
-    
-    let to_player = player_transform.translation - crim_transform.translation;
-    let distance = to_player.length();
-    let direction = to_player.normalize();
-    
-    // Make Crim face the player
-    if distance < 20.0 {
-        let target_rotation = Quat::from_rotation_arc(Vec3::Z, direction);
-        crim_transform.rotation = crim_transform.rotation.slerp(target_rotation, time.delta_secs() * 2.0);
+fn console_open(console: Res<ConsoleState>) -> bool {
+    console.open
+}
+
+// Parse a coordinate, `~` is relative to `current`
+fn parse_coord(arg: &str, current: f32) -> Result<f32, String> {
+    let bad = || format!("'{}' is not a coordinate", arg);
+    match arg.strip_prefix('~') {
+        Some("") => Ok(current),
+        Some(offset) => offset.parse::<f32>().map(|offset| current + offset).map_err(|_| bad()),
+        None => arg.parse().map_err(|_| bad()),
     }
-    
-    // Advanced line of sight check with raycasting
-    let crim_pos = crim_transform.translation;
-    let player_pos = player_transform.translation;
-    
-    // Step from Crim to player position checking for blocks
-    let ray_direction = (player_pos - crim_pos).normalize();
-    let ray_length = (player_pos - crim_pos).length();
-    let ray_steps = (ray_length / 0.5).ceil() as i32; // Check every 0.5 units
-    
-    let mut can_see_player = true;
-    
-    // Simple raycast implementation
-    'ray_check: for i in 1..ray_steps {
-        let check_pos = crim_pos + ray_direction * (i as f32 * 0.5);
-        let block_pos = (
-            (check_pos.x / BLOCK_SIZE).floor() as i32,
-            (check_pos.y / BLOCK_SIZE).floor() as i32,
-            (check_pos.z / BLOCK_SIZE).floor() as i32,
-        );
-        
-        if game_world.blocks.contains_key(&block_pos) {
-            // Ray hit a block, can't see player
-            can_see_player = false;
-            break 'ray_check;
-        }
+}
+
+fn parse_position(args: &[String], current: Vec3) -> Result<Vec3, String> {
+    let [x, y, z] = args else {
+        return Err("expected x y z".to_string());
+    };
+    Ok(Vec3::new(
+        parse_coord(x, current.x)?,
+        parse_coord(y, current.y)?,
+        parse_coord(z, current.z)?,
+    ))
+}
+
+fn parse_cell(args: &[String], current: Vec3) -> Result<(i32, i32, i32), String> {
+    parse_position(args, current).map(block_coords)
+}
+
+fn parse_count(arg: Option<&String>) -> Result<u32, String> {
+    match arg {
+        Some(count) => count.parse().map_err(|_| format!("'{}' is not a count", count)),
+        None => Ok(1),
     }
-    
-    // Crim behavior logic
-    if can_see_player && distance < 15.0 {
-        // Just spotted player
-        if !crim.spotted_player {
-            crim.spotted_player = true;
-            
-            // Emit particles when spotting player
-            let mut rng = rand::rng();
-            for _ in 0..5 {
-                let random_dir = Vec3::new(
-                    rng.random::<f32>() * 2.0 - 1.0,
-                    rng.random::<f32>() * 2.0 - 1.0,
-                    rng.random::<f32>() * 2.0 - 1.0,
-                ).normalize();
-                
-                commands.spawn((
-                    Mesh3d(meshes.add(Sphere::new(0.1))),
-                    MeshMaterial3d(materials.add(StandardMaterial {
-                        base_color: Color::srgb(1.0, 0.3, 0.0),
-                        emissive: Color::srgb(1.0, 0.3, 0.0).into(),
-                        ..default()
-                    })),
-                    Transform::from_translation(crim_pos + Vec3::new(0.0, 0.5, 0.0)),
-                    ParticleEffect {
-                        lifetime: 1.0,
-                        velocity: random_dir * 2.0,
-                        created: 0.0,
-                    },
-                ));
+}
+
+// `air` removes the block
+fn parse_block(arg: &str) -> Result<Option<BlockType>, String> {
+    if arg.eq_ignore_ascii_case("air") {
+        return Ok(None);
+    }
+    BlockType::from_name(arg).map(Some).ok_or_else(|| format!("unknown block '{}'", arg))
+}
+
+// Swap whatever is in `cell` for `block_type`, keeping the block entities in step
+fn set_block(
+    commands: &mut Commands,
+    block_assets: &BlockAssets,
+    game_world: &mut GameWorld,
+    block_entities: &HashMap<(i32, i32, i32), Entity>,
+    cell: (i32, i32, i32),
+    block_type: Option<BlockType>,
+) {
+    // Whatever a replaced chest held ends up on the ground rather than vanishing
+    spill_container(commands, block_assets, game_world, cell);
+
+    // Replacing either half of a door takes the whole door away
+    let cells = game_world.door_cells(cell);
+    let cells = if cells.is_empty() { vec![cell] } else { cells };
+    for cell in cells {
+        if game_world.remove_block(cell).is_some() {
+            if let Some(entity) = block_entities.get(&cell) {
+                commands.entity(*entity).despawn();
             }
         }
-        
-        // Chase player
-        crim_transform.translation += direction * CRIM_SPEED * time.delta_secs();
-        crim.chase_timer = 3.0; // Continue chasing for 3 seconds after losing sight
-    } else if crim.chase_timer > 0.0 {
-        // Continue chasing for a bit even if player is out of sight
-        crim_transform.translation += direction * CRIM_SPEED * 0.7 * time.delta_secs();
-        crim.chase_timer -= time.delta_secs();
-        
-        if crim.chase_timer <= 0.0 {
-            crim.spotted_player = false;
-        }
-    } else {
-        // Lost sight of player
-        crim.spotted_player = false;
+    }
+    if let Some(block_type) = block_type {
+        game_world.place_block(cell, block_type);
+        spawn_block(commands, block_assets, cell, block_type);
     }
 }
 
-fn block_interaction(
-    mouse_button: Res<ButtonInput<MouseButton>>,
-    player_query: Query<&Transform, With<Player>>,
-    camera_query: Query<&Transform, With<MainCamera>>,
+const MAX_FILL_VOLUME: i32 = 4096;
+
+fn register_world_commands(mut registry: ResMut<CommandRegistry>) {
+    registry.register("help", "/help", no_completions);
+    registry.register("tp", "/tp <x> <y> <z>", no_completions);
+    registry.register("give", "/give <block> [count]", |arg| if arg == 0 { block_names() } else { Vec::new() });
+    registry.register("setblock", "/setblock <x> <y> <z> <block|air>", |arg| {
+        if arg == 3 { [block_names(), vec!["air".to_string()]].concat() } else { Vec::new() }
+    });
+    registry.register("fill", "/fill <x1> <y1> <z1> <x2> <y2> <z2> <block|air>", |arg| {
+        if arg == 6 { [block_names(), vec!["air".to_string()]].concat() } else { Vec::new() }
+    });
+    registry.register("time", "/time set <day|night|noon|midnight|0-1>", |arg| match arg {
+        0 => vec!["set".to_string()],
+        1 => ["day", "night", "noon", "midnight"].map(String::from).to_vec(),
+        _ => Vec::new(),
+    });
+    registry.register("gamemode", "/gamemode <survival|creative|spectator>", |arg| {
+        if arg == 0 { ["survival", "creative", "spectator"].map(String::from).to_vec() } else { Vec::new() }
+    });
+    registry.register("seed", "/seed", no_completions);
+}
+
+fn world_commands(
+    mut command_events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<ConsoleState>,
+    registry: Res<CommandRegistry>,
     mut commands: Commands,
-    mut meshes: ResMut<Assets<Mesh>>,
-    mut materials: ResMut<Assets<StandardMaterial>>,
-    mut game_world: ResMut<GameWorld>,
-    blocks_query: Query<(Entity, &Position, &Block)>,
-    player_components: Query<&Player>,
+    mut player_query: Query<(&mut Transform, &mut Player)>,
     mut player_stats: ResMut<PlayerStats>,
+    mut game_world: ResMut<GameWorld>,
+    blocks_query: Query<(Entity, &Position), With<Block>>,
+    block_assets: Res<BlockAssets>,
+    mut time_of_day: ResMut<TimeOfDay>,
+    mut game_settings: ResMut<GameSettings>,
+    mut run_score: ResMut<RunScore>,
 ) {
-    let player = player_components.single();
-    
-    if !player.has_pickaxe {
-        return;
-    }
-    
-    let player_transform = player_query.single();
-    let camera_transform = camera_query.single();
-    
-    // Mining blocks
-    if mouse_button.just_pressed(MouseButton::Left) {
-        // Use camera position and direction for better aiming
-        let camera_pos = camera_transform.translation + player_transform.translation;
-        let camera_forward = camera_transform.forward();
-        
-        // Add mining animation by spawning particles
-        commands.spawn((
-            Mesh3d(meshes.add(RegularPolygon::new(0.05, 3))),
-            MeshMaterial3d(materials.add(Color::srgb(1.0, 0.8, 0.0))),
-            Transform::from_translation(camera_pos + camera_forward * 1.5),
-            ParticleEffect { 
-                lifetime: 0.5,
-                velocity: camera_forward * 0.5, 
-                created: 0.0,
-            },
-        ));
-        
-        let max_reach = 5.0;
-        let mut closest_block = None;
-        let mut closest_distance = max_reach;
-        
-        for (entity, position, _) in blocks_query.iter() {
-            let block_pos = Vec3::new(
-                position.x as f32 * BLOCK_SIZE,
-                position.y as f32 * BLOCK_SIZE,
-                position.z as f32 * BLOCK_SIZE,
-            );
-            
-            let distance = camera_pos.distance(block_pos);
-            
-            if distance < closest_distance {
-                let to_block = (block_pos - camera_pos).normalize();
-                
-                if camera_forward.dot(to_block) > 0.7 {
-                    closest_block = Some((entity, position.clone()));
-                    closest_distance = distance;
+    for event in command_events.read() {
+        let args = &event.args;
+        let Ok((mut player_transform, mut player)) = player_query.get_single_mut() else {
+            continue;
+        };
+        let player_pos = player_transform.translation;
+
+        let result = match event.name.as_str() {
+            "help" => {
+                for command in &registry.commands {
+                    console.print(command.usage);
                 }
+                continue;
             }
-        }
-        
-        if let Some((entity, position)) = closest_block {
-            // Get the block type
-            if let Ok((_, _, block)) = blocks_query.get(entity) {
-                let block_type = block.block_type;
-                
-                // Remove the block from the world
-                game_world.blocks.remove(&(position.x, position.y, position.z));
-                
-                // Add block to inventory
-                let entry = player_stats.inventory.entry(block_type).or_insert(0);
-                *entry += 1;
-                
-                commands.entity(entity).despawn();
-            }
-        }
-    }
-    
-    // Placing blocks
-    if mouse_button.just_pressed(MouseButton::Right) {
-        // Use camera for block placement
-        let camera_pos = camera_transform.translation + player_transform.translation;
-        let camera_forward = camera_transform.forward();
-        
-        // Raycast to find where to place the block
-        let ray_start = camera_pos;
-        let _ray_end = camera_pos + camera_forward * 5.0;
-        
-        // Simple raycast implementation for block placement
-        let mut ray_pos = ray_start;
-        let ray_step = camera_forward * 0.1;
-        let mut block_to_place = None;
-        let mut last_empty_pos = None;
-        
-        for _ in 0..50 {  // 50 steps for max reach of 5.0
-            let block_x = (ray_pos.x / BLOCK_SIZE).floor() as i32;
-            let block_y = (ray_pos.y / BLOCK_SIZE).floor() as i32;
-            let block_z = (ray_pos.z / BLOCK_SIZE).floor() as i32;
-            
-            let block_pos = (block_x, block_y, block_z);
-            
-            // If we hit a block, we want to place in the last empty position
-            if game_world.blocks.contains_key(&block_pos) {
-                block_to_place = last_empty_pos;
-                break;
-            }
-            
-            last_empty_pos = Some(block_pos);
-            ray_pos += ray_step;
-        }
-        
-        if let Some(block_pos) = block_to_place {
-            // Check if there's already a block at this position
-            if !game_world.blocks.contains_key(&block_pos) {
-                // Check if player has this block type in inventory
-                let has_block = player_stats.inventory.get(&player.selected_block_type).copied().unwrap_or(0) > 0;
-                
-                if has_block {
-                    // Remove block from inventory
-                    if let Some(count) = player_stats.inventory.get_mut(&player.selected_block_type) {
-                        *count -= 1;
-                        if *count == 0 {
-                            player_stats.inventory.remove(&player.selected_block_type);
-                        }
-                    }
-                    
-                    // Add a new block of the selected type
-                    game_world.blocks.insert(block_pos, player.selected_block_type);
-                    
-                    // Use player's selected block type
-                    let material = match player.selected_block_type {
-                    BlockType::Dirt => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.6, 0.3, 0.1),
-                        ..default()
-                    }),
-                    BlockType::Stone => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.5, 0.5, 0.5),
-                        ..default()
-                    }),
-                    BlockType::Wood => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.6, 0.4, 0.2),
-                        ..default()
-                    }),
-                    BlockType::Grass => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.3, 0.7, 0.2),
-                        ..default()
-                    }),
-                    BlockType::Sand => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.9, 0.8, 0.5),
-                        ..default()
-                    }),
-                    BlockType::Water => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.2, 0.4, 0.8),
-                        alpha_mode: AlphaMode::Blend,
-                        metallic: 0.0,
-                        perceptual_roughness: 0.1,
-                        reflectance: 0.5,
-                        ..default()
-                    }),
-                    BlockType::Ore => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.4, 0.2, 0.6),
-                        metallic: 0.7,
-                        perceptual_roughness: 0.1,
-                        ..default()
+            "tp" => match args.as_slice() {
+                [_, _, _] => parse_position(args, player_pos).map(|target| {
+                    player_transform.translation = target;
+                    player.velocity = Vec3::ZERO;
+                    run_score.cheated = true;
+                    format!("Teleported to {:.1} {:.1} {:.1}", target.x, target.y, target.z)
+                }),
+                _ => Err("usage: /tp <x> <y> <z>".to_string()),
+            },
+            "give" => match args.as_slice() {
+                [name] | [name, _] => parse_block(name)
+                    .and_then(|block_type| block_type.ok_or_else(|| "cannot give air".to_string()))
+                    .and_then(|block_type| parse_count(args.get(1)).map(|count| (block_type, count)))
+                    .map(|(block_type, count)| {
+                        let stack = player_stats.inventory.entry(block_type).or_insert(0);
+                        *stack = stack.saturating_add(count);
+                        run_score.cheated = true;
+                        format!("Gave {} {:?}", count, block_type)
                     }),
-                    BlockType::Glass => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.8, 0.9, 1.0),
-                        alpha_mode: AlphaMode::Blend,
-                        metallic: 0.0,
-                        perceptual_roughness: 0.0,
-                        reflectance: 0.5,
-                        ..default()
+                _ => Err("usage: /give <block> [count]".to_string()),
+            },
+            "setblock" => match args.as_slice() {
+                [_, _, _, name] => parse_cell(&args[..3], player_pos)
+                    .and_then(|cell| parse_block(name).map(|block_type| (cell, block_type)))
+                    .map(|(cell, block_type)| {
+                        let block_entities = blocks_query.iter()
+                            .map(|(entity, position)| ((position.x, position.y, position.z), entity))
+                            .collect();
+                        set_block(&mut commands, &block_assets, &mut game_world, &block_entities, cell, block_type);
+                        run_score.cheated = true;
+                        format!("Set block at {} {} {}", cell.0, cell.1, cell.2)
                     }),
-                    BlockType::Obsidian => materials.add(StandardMaterial {
-                        base_color: Color::srgb(0.1, 0.1, 0.2),
-                        metallic: 0.2,
-                        perceptual_roughness: 0.1,
-                        ..default()
+                _ => Err("usage: /setblock <x> <y> <z> <block|air>".to_string()),
+            },
+            "fill" => match args.as_slice() {
+                [_, _, _, _, _, _, name] => parse_cell(&args[..3], player_pos)
+                    .and_then(|from| parse_cell(&args[3..6], player_pos).map(|to| (from, to)))
+                    .and_then(|(from, to)| parse_block(name).map(|block_type| (from, to, block_type)))
+                    .and_then(|(from, to, block_type)| {
+                        let min = (from.0.min(to.0), from.1.min(to.1), from.2.min(to.2));
+                        let max = (from.0.max(to.0), from.1.max(to.1), from.2.max(to.2));
+                        let volume = (max.0 - min.0 + 1) * (max.1 - min.1 + 1) * (max.2 - min.2 + 1);
+                        if volume > MAX_FILL_VOLUME {
+                            return Err(format!("{} blocks is too many, the limit is {}", volume, MAX_FILL_VOLUME));
+                        }
+
+                        let block_entities: HashMap<_, _> = blocks_query.iter()
+                            .map(|(entity, position)| ((position.x, position.y, position.z), entity))
+                            .collect();
+                        for x in min.0..=max.0 {
+                            for y in min.1..=max.1 {
+                                for z in min.2..=max.2 {
+                                    set_block(&mut commands, &block_assets, &mut game_world, &block_entities, (x, y, z), block_type);
+                                }
+                            }
+                        }
+                        run_score.cheated = true;
+                        Ok(format!("Filled {} blocks", volume))
                     }),
-                };
-                
-                commands.spawn((
-                    Mesh3d(meshes.add(Cuboid::default())),
-                    MeshMaterial3d(material),
-                    Transform::from_xyz(
-                        block_pos.0 as f32 * BLOCK_SIZE,
-                        block_pos.1 as f32 * BLOCK_SIZE,
-                        block_pos.2 as f32 * BLOCK_SIZE,
-                    ),
-                    Block { block_type: player.selected_block_type },
-                    Position { x: block_pos.0, y: block_pos.1, z: block_pos.2 },
-                ));
+                _ => Err("usage: /fill <x1> <y1> <z1> <x2> <y2> <z2> <block|air>".to_string()),
+            },
+            "time" => match args.as_slice() {
+                [set, value] if set == "set" => {
+                    let time = match value.as_str() {
+                        "day" => Ok(0.3),
+                        "noon" => Ok(0.5),
+                        "night" => Ok(0.8),
+                        "midnight" => Ok(0.0),
+                        other => other.parse::<f32>()
+                            .ok()
+                            .filter(|time| (0.0..=1.0).contains(time))
+                            .ok_or_else(|| format!("'{}' is not a time of day", other)),
+                    };
+                    time.map(|time| {
+                        time_of_day.time = time;
+                        run_score.cheated = true;
+                        let (hours, minutes) = time_of_day.clock();
+                        format!("Time set to {:02}:{:02}", hours, minutes)
+                    })
                 }
-            }
-        }
+                _ => Err("usage: /time set <day|night|noon|midnight|0-1>".to_string()),
+            },
+            "gamemode" => match args.as_slice() {
+                [name] => {
+                    match GameMode::from_name(name) {
+                        Some(game_mode) => {
+                            game_settings.game_mode = game_mode;
+                            run_score.cheated = true;
+                            Ok(format!("Game mode set to {:?}", game_mode))
+                        }
+                        None => Err(format!("unknown game mode '{}'", name)),
+                    }
+                }
+                _ => Err("usage: /gamemode <survival|creative|spectator>".to_string()),
+            },
+            "seed" => Ok(format!("Seed: {}", game_world.seed)),
+            _ => continue,
+        };
+
+        console.reply(result);
     }
 }
 
-fn camera_control(
-    mut windows: Query<&mut Window>,
-    mut mouse_motion_events: EventReader<MouseMotion>,
-    mut query: Query<&mut Transform, With<MainCamera>>,
-) {
-    let mut rotation_move = Vec2::ZERO;
-    
-    // Get mouse delta
-    for event in mouse_motion_events.read() {
-        rotation_move += event.delta;
-    }
-    
-    if rotation_move.length_squared() > 0.0 {
-        let mut transform = query.single_mut();
-        
-        // Apply mouse delta as camera rotation (pitch and yaw)
-        let (mut yaw, mut pitch, _) = transform.rotation.to_euler(EulerRot::YXZ);
-        
-        // Increment yaw and pitch based on mouse movement
-        yaw -= rotation_move.x * MOUSE_SENSITIVITY;
-        pitch -= rotation_move.y * MOUSE_SENSITIVITY;
-        
-        // Clamp pitch to avoid camera flipping
-        pitch = pitch.clamp(-1.5, 1.5);
-        
-        // Apply rotations
-        transform.rotation = Quat::from_euler(EulerRot::YXZ, yaw, pitch, 0.0);
-        
-        // Grab cursor for continuous rotation
-        let mut window = windows.single_mut();
-        window.cursor_options.grab_mode = bevy::window::CursorGrabMode::Locked;
-        window.cursor_options.visible = false;
-    }
+// Lets the console stop Crim in place for debugging
+#[derive(Resource, Default)]
+struct CrimDebug {
+    frozen: bool,
 }
 
-// Apply gravity and handle collisions
-fn physics_system(
-    time: Res<Time>,
-    game_world: Res<GameWorld>,
-    mut player_query: Query<(&mut Transform, &mut Player)>,
+fn crims_unfrozen(crim_debug: Res<CrimDebug>) -> bool {
+    !crim_debug.frozen
+}
+
+fn register_crim_commands(mut registry: ResMut<CommandRegistry>) {
+    registry.register("crim", "/crim <spawn|freeze|kill>", |arg| {
+        if arg == 0 { ["spawn", "freeze", "kill"].map(String::from).to_vec() } else { Vec::new() }
+    });
+}
+
+fn crim_commands(
+    mut command_events: EventReader<ConsoleCommandEvent>,
+    mut console: ResMut<ConsoleState>,
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    player_query: Query<&Transform, With<Player>>,
+    crim_query: Query<Entity, With<Crim>>,
+    mut crim_debug: ResMut<CrimDebug>,
     game_settings: Res<GameSettings>,
+    mut run_score: ResMut<RunScore>,
 ) {
-    if !game_settings.gravity_enabled {
-        return;
-    }
+    for event in command_events.read() {
+        if event.name != "crim" {
+            continue;
+        }
 
-    let gravity = Vec3::new(0.0, -9.8, 0.0);
-    let (mut transform, mut player) = player_query.single_mut();
-    
-    // Apply gravity to velocity
-    player.velocity += gravity * time.delta_secs();
-    
-    // Apply velocity to position
-    let potential_position = transform.translation + player.velocity * time.delta_secs();
-    
-    // Check for collisions
-    let block_pos_x = (potential_position.x / BLOCK_SIZE).floor() as i32;
-    let block_pos_y = (potential_position.y / BLOCK_SIZE).floor() as i32;
-    let block_pos_z = (potential_position.z / BLOCK_SIZE).floor() as i32;
-    
-    // Check if we're inside a block
-    let collision = game_world.blocks.contains_key(&(block_pos_x, block_pos_y, block_pos_z));
-    
-    // Check if we're standing on a block (grounded)
-    let block_below = game_world.blocks.contains_key(&(block_pos_x, block_pos_y - 1, block_pos_z));
-    
-    // Handle collision response
-    if collision {
-        // Stop falling
-        player.velocity.y = 0.0;
-    } else {
-        // Apply velocity
-        transform.translation = potential_position;
-    }
-    
-    // Update grounded state
-    player.is_grounded = block_below;
-    
-    // Dampen velocity if grounded
-    if player.is_grounded && player.velocity.y <= 0.0 {
-        player.velocity.y = 0.0;
+        let result = match event.args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+            ["spawn"] if game_settings.game_mode != GameMode::Survival => {
+                Err("Crim only comes out in survival".to_string())
+            }
+            ["spawn"] => match player_query.get_single() {
+                Ok(player_transform) => {
+                    // A few blocks in front of the player
+                    let position = player_transform.translation + player_transform.forward() * 5.0 + Vec3::Y;
+                    spawn_crim(
+                        &mut commands,
+                        &mut meshes,
+                        &mut materials,
+                        position,
+                        CRIM_SPEED * game_settings.difficulty.crim_speed_multiplier(),
+                    );
+                    run_score.cheated = true;
+                    Ok("Spawned a Crim".to_string())
+                }
+                Err(_) => Err("no player to spawn near".to_string()),
+            },
+            ["freeze"] => {
+                crim_debug.frozen = !crim_debug.frozen;
+                run_score.cheated = true;
+                Ok(if crim_debug.frozen { "Crims frozen" } else { "Crims unfrozen" }.to_string())
+            }
+            ["kill"] => {
+                let mut count = 0;
+                for entity in crim_query.iter() {
+                    commands.entity(entity).despawn_recursive();
+                    count += 1;
+                }
+                run_score.cheated = true;
+                Ok(format!("Killed {} Crims", count))
+            }
+            _ => Err("usage: /crim <spawn|freeze|kill>".to_string()),
+        };
+
+        console.reply(result);
     }
 }
 
-// Switch between block types
-fn block_selection_system(
-    keyboard_input: Res<ButtonInput<KeyCode>>,
-    mut player_query: Query<&mut Player>,
+// Open the console with `/` already typed
+fn console_toggle(
+    actions: Res<ButtonInput<InputAction>>,
+    mut console: ResMut<ConsoleState>,
+    mut windows: Query<&mut Window>,
 ) {
-    let mut player = player_query.single_mut();
-    
-    // Number keys to select different block types
-    if keyboard_input.just_pressed(KeyCode::Digit1) {
-        player.selected_block_type = BlockType::Dirt;
-    } else if keyboard_input.just_pressed(KeyCode::Digit2) {
-        player.selected_block_type = BlockType::Stone;
-    } else if keyboard_input.just_pressed(KeyCode::Digit3) {
-        player.selected_block_type = BlockType::Wood;
-    } else if keyboard_input.just_pressed(KeyCode::Digit4) {
-        player.selected_block_type = BlockType::Grass;
-    } else if keyboard_input.just_pressed(KeyCode::Digit5) {
-        player.selected_block_type = BlockType::Sand;
-    } else if keyboard_input.just_pressed(KeyCode::Digit6) {
-        player.selected_block_type = BlockType::Glass;
-    } else if keyboard_input.just_pressed(KeyCode::Digit7) {
-        player.selected_block_type = BlockType::Obsidian;
-    } else if keyboard_input.just_pressed(KeyCode::Digit8) {
-        player.selected_block_type = BlockType::Ore;
-    } else if keyboard_input.just_pressed(KeyCode::Digit9) {
-        player.selected_block_type = BlockType::Water;
+    if !actions.just_pressed(InputAction::OpenConsole) {
+        return;
+    }
+
+    console.open = true;
+    console.input = "/".to_string();
+    console.history_index = None;
+    if let Ok(mut window) = windows.get_single_mut() {
+        window.cursor_options.grab_mode = bevy::window::CursorGrabMode::None;
+        window.cursor_options.visible = true;
     }
 }
 
-// Display UI
-fn ui_system(
-    _commands: Commands,
-    game_ui: Res<GameUI>,
-    player_query: Query<&Player>,
+fn console_ui(
     mut contexts: EguiContexts,
-    player_stats: Res<PlayerStats>,
-    game_settings: Res<GameSettings>,
-    game_world: Res<GameWorld>,
+    mut console: ResMut<ConsoleState>,
+    registry: Res<CommandRegistry>,
+    mut command_events: EventWriter<ConsoleCommandEvent>,
+    mut windows: Query<&mut Window>,
 ) {
-    if game_ui.show_debug {
-        let player = player_query.single();
-        let ctx = contexts.ctx_mut();
-        
-        egui::Window::new("Debug Info").show(ctx, |ui| {
-            ui.label(format!("Selected Block: {:?}", player.selected_block_type));
-            ui.label(format!("Is Grounded: {}", player.is_grounded));
-            ui.label(format!("Velocity: {:?}", player.velocity));
-            ui.separator();
-            ui.label(format!("Health: {}/{}", player_stats.health, player_stats.max_health));
-            ui.label(format!("Inventory Items: {}", player_stats.inventory.len()));
-            ui.separator();
-            ui.label(format!("Render Distance: {}", game_settings.render_distance));
-            ui.label(format!("Gravity Enabled: {}", game_settings.gravity_enabled));
-            ui.separator();
-            ui.label(format!("Total Blocks: {}", game_world.blocks.len()));
-            
-            // Display blocks in inventory
-            if !player_stats.inventory.is_empty() {
-                ui.collapsing("Inventory", |ui| {
-                    for (block_type, count) in &player_stats.inventory {
-                        ui.label(format!("{:?}: {}", block_type, count));
+    let console = console.as_mut();
+    let mut close = false;
+
+    egui::Window::new("Console")
+        .anchor(egui::Align2::LEFT_TOP, egui::vec2(10.0, 10.0))
+        .default_width(500.0)
+        .collapsible(false)
+        .show(contexts.ctx_mut(), |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(250.0)
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    for (line, error) in &console.log {
+                        if *error {
+                            ui.colored_label(egui::Color32::RED, line);
+                        } else {
+                            ui.label(line);
+                        }
                     }
                 });
+
+            let response = ui.add(
+                egui::TextEdit::singleline(&mut console.input)
+                    .desired_width(f32::INFINITY)
+                    .lock_focus(true),
+            );
+            response.request_focus();
+
+            let suggestions = registry.completions(&console.input);
+            if !suggestions.is_empty() && console.input.len() > 1 {
+                ui.weak(suggestions.join("  "));
+            }
+
+            let (enter, escape, tab, up, down) = ui.input(|i| (
+                i.key_pressed(egui::Key::Enter),
+                i.key_pressed(egui::Key::Escape),
+                i.key_pressed(egui::Key::Tab),
+                i.key_pressed(egui::Key::ArrowUp),
+                i.key_pressed(egui::Key::ArrowDown),
+            ));
+
+            if escape {
+                close = true;
+            } else if enter {
+                let line = console.input.trim().to_string();
+                console.input = "/".to_string();
+                console.history_index = None;
+                if line.trim_start_matches('/').is_empty() {
+                    return;
+                }
+
+                console.print(format!("> {}", line));
+                console.history.push(line.clone());
+
+                let mut words = line.trim_start_matches('/').split_whitespace().map(str::to_string);
+                let name = words.next().unwrap_or_default().to_lowercase();
+                if registry.find(&name).is_some() {
+                    command_events.send(ConsoleCommandEvent { name, args: words.collect() });
+                } else {
+                    console.error(format!("Unknown command '/{}', try /help", name));
+                }
+            } else if tab {
+                // Fill in the only match, or as much as all matches share
+                if let Some(first) = suggestions.first() {
+                    let shared = suggestions.iter().fold(first.clone(), |shared, candidate| {
+                        shared.chars().zip(candidate.chars()).take_while(|(a, b)| a == b).map(|(a, _)| a).collect()
+                    });
+                    console.input = complete_last_word(&console.input, &shared);
+                    if suggestions.len() == 1 {
+                        console.input.push(' ');
+                    }
+                    if let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), response.id) {
+                        let end = egui::text::CCursor::new(console.input.chars().count());
+                        state.cursor.set_char_range(Some(egui::text::CCursorRange::one(end)));
+                        state.store(ui.ctx(), response.id);
+                    }
+                }
+            } else if (up || down) && !console.history.is_empty() {
+                let last = console.history.len() - 1;
+                let index = match (console.history_index, up) {
+                    (None, true) => Some(last),
+                    (None, false) => None,
+                    (Some(index), true) => Some(index.saturating_sub(1)),
+                    (Some(index), false) if index < last => Some(index + 1),
+                    (Some(_), false) => None,
+                };
+                console.history_index = index;
+                console.input = index.map_or("/".to_string(), |index| console.history[index].clone());
             }
         });
-    }
-    
-    if game_ui.show_crosshair {
-        // Add crosshair in the center of the screen using Bevy UI
-        let ctx = contexts.ctx_mut();
-        
-        // Simple crosshair in the center
-        let screen_size = ctx.screen_rect().size();
-        let center = egui::pos2(screen_size.x / 2.0, screen_size.y / 2.0);
-        
-        ctx.debug_painter().circle_filled(
-            center, 
-            2.0, 
-            egui::Color32::WHITE
-        );
-    }
-}
 
-// System to animate and despawn particles
-fn particle_system(
-    mut commands: Commands,
-    mut particles: Query<(Entity, &mut Transform, &mut ParticleEffect)>,
-    time: Res<Time>,
-) {
-    for (entity, mut transform, mut particle) in particles.iter_mut() {
-        // Update age
-        if particle.created == 0.0 {
-            particle.created = time.elapsed_secs();
-        }
-        
-        let age = time.elapsed_secs() - particle.created;
-        
-        // Apply velocity
-        transform.translation += particle.velocity * time.delta_secs();
-        
-        // Add some gravity effect to particles
-        particle.velocity.y -= 2.0 * time.delta_secs();
-        
-        // Remove particle if it's too old
-        if age > particle.lifetime {
-            commands.entity(entity).despawn();
+    if close {
+        console.open = false;
+        if let Ok(mut window) = windows.get_single_mut() {
+            window.cursor_options.grab_mode = bevy::window::CursorGrabMode::Locked;
+            window.cursor_options.visible = false;
         }
     }
 }
 
-// Setup a skybox and ambient lighting
-fn setup_environment(
-    mut commands: Commands,
-    _meshes: ResMut<Assets<Mesh>>,
-    _materials: ResMut<Assets<StandardMaterial>>,
-) {
-    // Add ambient light
-    commands.insert_resource(AmbientLight {
-        color: Color::srgb(0.6, 0.6, 0.9),
-        brightness: 0.3,
-    });
-    
-    // Add a distant directional light for sun effect
-    commands.spawn((
-        DirectionalLight {
-            color: Color::srgb(1.0, 0.95, 0.8),
-            illuminance: 10000.0,
-            shadows_enabled: true,
-            ..default()
-        },
-        Transform::from_xyz(10.0, 50.0, 10.0)
-            .looking_at(Vec3::ZERO, Vec3::Y),
-    ));
-}
-
 fn main() {
+    let game_settings = GameSettings::load();
+
     App::new()
         .add_plugins(DefaultPlugins.set(WindowPlugin {
             primary_window: Some(Window {
                 title: "CrimCraft - Minecraft-like Demo".to_string(),
-                resolution: (1280., 720.).into(),
+                resolution: (game_settings.window_width, game_settings.window_height).into(),
+                present_mode: game_settings.present_mode(),
+                mode: game_settings.window_mode.window_mode(),
                 ..default()
             }),
             ..default()
@@ -1000,30 +5684,469 @@ fn main() {
         .add_plugins(EguiPlugin)
         .insert_resource(GameWorld {
             blocks: HashMap::new(),
+            seed: 0,
+            changed: Vec::new(),
+            sky_light: HashMap::new(),
+            block_light: HashMap::new(),
+            light_changed: HashSet::new(),
+            redraw: HashSet::new(),
+            containers: HashMap::new(),
         })
         .insert_resource(PlayerStats {
             health: 100.0,
             max_health: 100.0,
+            breath: MAX_BREATH,
             inventory: HashMap::new(),
         })
         .insert_resource(GameUI {
             show_debug: true,
             show_crosshair: true,
+            show_ai_debug: false,
+            show_settings: false,
+            show_crafting: false,
+            open_chest: None,
+            rebinding: None,
         })
-        .insert_resource(GameSettings {
-            render_distance: 10,
-            gravity_enabled: true,
+        .insert_resource(game_settings)
+        .insert_resource(InputBindings::load())
+        .init_resource::<ButtonInput<InputAction>>()
+        .init_resource::<ActionAxes>()
+        .init_resource::<CameraRig>()
+        .init_resource::<CommandRegistry>()
+        .init_resource::<ConsoleState>()
+        .init_resource::<CrimDebug>()
+        .init_resource::<BlockTicks>()
+        .add_event::<ConsoleCommandEvent>()
+        .init_resource::<Shelter>()
+        .init_resource::<TimeOfDay>()
+        .init_resource::<WorldSave>()
+        .init_resource::<RunScore>()
+        .insert_resource(HighScores::load())
+        .add_event::<NoiseEvent>()
+        .add_event::<SaveGameEvent>()
+        .insert_resource(CrimSpawner {
+            timer: CRIM_SPAWN_INTERVAL,
         })
-        .add_systems(Startup, (setup, setup_environment))
+        .init_state::<GameState>()
+        .add_systems(Startup, (setup_environment, register_world_commands, register_crim_commands))
+        .add_systems(PreUpdate, update_actions.after(bevy::input::InputSystem))
+        .add_systems(OnEnter(GameState::Loading), (cleanup_world, setup).chain())
+        .add_systems(OnEnter(GameState::Playing), grab_cursor)
+        .add_systems(OnExit(GameState::Playing), release_cursor)
+        .add_systems(OnExit(GameState::MainMenu), close_settings)
+        .add_systems(OnExit(GameState::Paused), close_settings)
+        .add_systems(OnEnter(GameState::GameOver), record_high_score)
+        .add_systems(Update, (
+            main_menu_ui.run_if(in_state(GameState::MainMenu)),
+            (loading_ui, finish_loading).run_if(in_state(GameState::Loading)),
+            pause_menu_ui.run_if(in_state(GameState::Paused)),
+            game_over_ui.run_if(in_state(GameState::GameOver)),
+            pause_toggle,
+            save_game,
+            apply_settings,
+            settings_ui.run_if(in_state(GameState::MainMenu).or(in_state(GameState::Paused))),
+        ))
         .add_systems(Update, (
             player_movement,
-            crim_ai,
+            shelter_analysis,
+            ((crim_ai, crim_attack).run_if(crims_unfrozen), crim_spawner).run_if(crim_enabled),
+            sheep_ai,
             block_interaction,
-            camera_control,
+            interact_system,
+            drop_item_system,
+            dropped_item_system,
+            camera_control.run_if(not(console_open).and(not(crafting_open)).and(not(chest_open))),
             physics_system,
+            camera_rig_system.after(player_movement).after(physics_system).after(camera_control),
             block_selection_system,
+            block_tick_system,
+            block_light_system.after(block_tick_system).after(block_interaction),
+            falling_block_system,
+            breath_system,
+            particle_system,
+            day_night_cycle,
+            update_run,
+            check_game_over,
+        ).run_if(in_state(GameState::Playing)))
+        .add_systems(Update, (
+            console_toggle.run_if(not(console_open)),
+            console_ui.run_if(console_open),
+            crafting_toggle.run_if(not(console_open).and(not(chest_open))),
+            crafting_ui.run_if(crafting_open),
+            chest_ui.run_if(chest_open),
+            world_commands,
+            crim_commands,
+        ).run_if(in_state(GameState::Playing)))
+        .add_systems(Update, (
             ui_system,
-            particle_system
-        ))
+            ai_debug_system,
+            track_run_mode,
+            dismiss_crims,
+        ).run_if(in_state(GameState::Playing).or(in_state(GameState::Paused))))
         .run();
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just the input layer, no window or renderer
+    fn input_app() -> App {
+        let mut app = App::new();
+        app.add_plugins(MinimalPlugins)
+            .init_resource::<ButtonInput<KeyCode>>()
+            .init_resource::<ButtonInput<MouseButton>>()
+            .init_resource::<ButtonInput<InputAction>>()
+            .init_resource::<ActionAxes>()
+            .init_resource::<ConsoleState>()
+            .insert_resource(InputBindings::default())
+            .insert_resource(GameSettings::default())
+            .insert_resource(GameUI {
+                show_debug: false,
+                show_crosshair: true,
+                show_ai_debug: false,
+                show_settings: false,
+                show_crafting: false,
+                open_chest: None,
+                rebinding: None,
+            })
+            .add_systems(Update, update_actions);
+        app
+    }
+
+    #[test]
+    fn gamepad_drives_actions_and_axes() {
+        let mut app = input_app();
+        let mut gamepad = Gamepad::default();
+        gamepad.digital_mut().press(GamepadButton::South);
+        gamepad.analog_mut().set(GamepadAxis::LeftStickY, 1.0);
+        gamepad.analog_mut().set(GamepadAxis::RightStickX, 0.05);
+        let entity = app.world_mut().spawn(gamepad).id();
+
+        app.update();
+        let actions = app.world().resource::<ButtonInput<InputAction>>();
+        assert!(actions.just_pressed(InputAction::Jump));
+        assert!(!actions.pressed(InputAction::Descend));
+        let axes = app.world().resource::<ActionAxes>();
+        assert_eq!(axes.movement, Vec2::Y);
+        // Inside the deadzone, so no drift
+        assert_eq!(axes.look, Vec2::ZERO);
+
+        // Holding the button keeps the action pressed without pressing it again
+        app.world_mut().get_mut::<Gamepad>(entity).unwrap().digital_mut().clear();
+        app.update();
+        let actions = app.world().resource::<ButtonInput<InputAction>>();
+        assert!(actions.pressed(InputAction::Jump));
+        assert!(!actions.just_pressed(InputAction::Jump));
+
+        let mut gamepad = app.world_mut().get_mut::<Gamepad>(entity).unwrap();
+        gamepad.digital_mut().release(GamepadButton::South);
+        gamepad.analog_mut().set(GamepadAxis::LeftStickY, 0.0);
+        app.update();
+        let actions = app.world().resource::<ButtonInput<InputAction>>();
+        assert!(!actions.pressed(InputAction::Jump));
+        assert_eq!(app.world().resource::<ActionAxes>().movement, Vec2::ZERO);
+    }
+
+    #[test]
+    fn gamepad_is_ignored_while_the_console_is_open() {
+        let mut app = input_app();
+        app.world_mut().resource_mut::<ConsoleState>().open = true;
+        let mut gamepad = Gamepad::default();
+        gamepad.digital_mut().press(GamepadButton::South);
+        gamepad.analog_mut().set(GamepadAxis::LeftStickX, 1.0);
+        app.world_mut().spawn(gamepad);
+
+        app.update();
+        assert!(!app.world().resource::<ButtonInput<InputAction>>().pressed(InputAction::Jump));
+        assert_eq!(app.world().resource::<ActionAxes>().movement, Vec2::ZERO);
+    }
+
+    #[test]
+    fn deadzone_cuts_drift_and_keeps_full_range() {
+        assert_eq!(apply_deadzone(Vec2::new(0.1, 0.0), 0.15), Vec2::ZERO);
+        assert_eq!(apply_deadzone(Vec2::new(0.0, 1.0), 0.15), Vec2::Y);
+        assert_eq!(apply_deadzone(Vec2::new(-1.0, 0.0), 0.0), Vec2::NEG_X);
+
+        // Halfway between the deadzone and the edge is half speed, in the same direction
+        let stick = apply_deadzone(Vec2::new(0.6, 0.0), 0.2);
+        assert!((stick.x - 0.5).abs() < 1e-5 && stick.y == 0.0);
+
+        // Corners of a square gate don't go past full
+        assert!(apply_deadzone(Vec2::new(1.0, 1.0), 0.15).length() <= 1.0 + 1e-5);
+    }
+
+    #[test]
+    fn block_state_properties_keep_to_their_own_bits() {
+        let state = BlockState::from(BlockType::Door)
+            .with_level(5)
+            .with_facing(Facing::West)
+            .with_open(true)
+            .with_axis(Axis::Z)
+            .with_upper(true);
+        assert_eq!(state.level(), 5);
+        assert_eq!(state.facing(), Facing::West);
+        assert!(state.is_open());
+        assert_eq!(state.axis(), Axis::Z);
+        assert!(state.is_upper());
+
+        // Changing one property leaves the others alone
+        let state = state.with_level(2).with_open(false).with_facing(Facing::North);
+        assert_eq!(state.level(), 2);
+        assert_eq!(state.facing(), Facing::North);
+        assert!(!state.is_open());
+        assert_eq!(state.axis(), Axis::Z);
+        assert!(state.is_upper());
+
+        // Out of range levels are masked instead of spilling into the facing
+        let state = BlockState::from(BlockType::Water).with_level(0xff);
+        assert_eq!(state.level(), 7);
+        assert_eq!(state.facing(), Facing::North);
+        assert!(!state.is_open());
+        assert_eq!(state.with_upper(false).with_axis(Axis::Y), BlockState::from(BlockType::Water).with_level(7));
+    }
+
+    #[test]
+    fn save_round_trips() {
+        let mut containers = HashMap::new();
+        containers.insert((1, 2, 3), Container { items: HashMap::from([(BlockType::Ore, 4), (BlockType::Wood, 12)]) });
+        let save = SaveData {
+            seed: 1234,
+            time_of_day: 0.75,
+            day: 3,
+            player_position: Vec3::new(1.5, 12.0, -4.25),
+            health: 42.5,
+            breath: 3.0,
+            game_mode: GameMode::Creative,
+            objective: Objective::CollectOre(10),
+            beacon: Some((17, 3, -17)),
+            inventory: HashMap::from([(BlockType::Dirt, 20), (BlockType::Torch, 3)]),
+            blocks: HashMap::from([
+                ((0, 0, 0), BlockState::from(BlockType::Stone)),
+                ((0, 1, 0), BlockState::from(BlockType::Water).with_level(3)),
+                ((2, 1, 0), BlockState::from(BlockType::Door).with_facing(Facing::East).with_open(true)),
+                ((2, 2, 0), BlockState::from(BlockType::Door).with_facing(Facing::East).with_open(true).with_upper(true)),
+                ((4, 1, 0), BlockState::from(BlockType::Wood).with_axis(Axis::X)),
+                ((1, 2, 3), BlockState::from(BlockType::Chest)),
+            ]),
+            containers,
+            dropped_items: vec![(Vec3::new(0.5, 1.0, 2.5), BlockType::Wool, 3, false), (Vec3::new(1.5, 1.0, 2.5), BlockType::Ore, 1, true)],
+            run_score: RunScore {
+                time_survived: 95.5,
+                blocks_mined: 7,
+                ore_collected: 2,
+                close_calls: 1,
+                start_day: 1,
+                cheated: false,
+                left_survival: true,
+                ..default()
+            },
+        };
+
+        let path = std::env::temp_dir().join(format!("crimcraft-test-{}.sav", std::process::id()));
+        save.write(&path).unwrap();
+        let loaded = SaveData::read(&path);
+        let _ = std::fs::remove_file(&path);
+        let loaded = loaded.unwrap();
+
+        assert_eq!(loaded.seed, save.seed);
+        assert_eq!(loaded.time_of_day, save.time_of_day);
+        assert_eq!(loaded.day, save.day);
+        assert_eq!(loaded.player_position, save.player_position);
+        assert_eq!(loaded.health, save.health);
+        assert_eq!(loaded.breath, save.breath);
+        assert_eq!(loaded.game_mode, save.game_mode);
+        assert_eq!(loaded.objective, save.objective);
+        assert_eq!(loaded.beacon, save.beacon);
+        assert_eq!(loaded.dropped_items, save.dropped_items);
+        assert_eq!(loaded.inventory, save.inventory);
+        assert_eq!(loaded.blocks, save.blocks);
+        assert_eq!(loaded.containers.len(), 1);
+        assert_eq!(loaded.containers[&(1, 2, 3)].items, save.containers[&(1, 2, 3)].items);
+        assert_eq!(loaded.run_score.time_survived, save.run_score.time_survived);
+        assert_eq!(loaded.run_score.blocks_mined, save.run_score.blocks_mined);
+        assert_eq!(loaded.run_score.ore_collected, save.run_score.ore_collected);
+        assert_eq!(loaded.run_score.close_calls, save.run_score.close_calls);
+        assert_eq!(loaded.run_score.start_day, save.run_score.start_day);
+        assert_eq!(loaded.run_score.cheated, save.run_score.cheated);
+        assert_eq!(loaded.run_score.left_survival, save.run_score.left_survival);
+    }
+
+    #[test]
+    fn incremental_light_matches_a_full_relight() {
+        let mut game_world = GameWorld::default();
+        for x in -3..=3 {
+            for z in -3..=3 {
+                game_world.blocks.insert((x, 0, z), BlockType::Stone.into());
+            }
+        }
+        game_world.blocks.insert((2, 1, 0), BlockType::Stone.into());
+        game_world.relight_all();
+
+        let check = |game_world: &GameWorld| {
+            let mut fresh = GameWorld { blocks: game_world.blocks.clone(), ..default() };
+            fresh.relight_all();
+            assert_eq!(game_world.sky_light, fresh.sky_light);
+            assert_eq!(game_world.block_light, fresh.block_light);
+        };
+
+        // A roof over a lamp, then take them away again in the same order
+        game_world.place_block((0, 3, 0), BlockType::Stone);
+        check(&game_world);
+        game_world.place_block((0, 1, 0), BlockType::Lantern);
+        check(&game_world);
+        game_world.remove_block((0, 3, 0));
+        check(&game_world);
+        game_world.remove_block((0, 1, 0));
+        check(&game_world);
+    }
+
+    #[test]
+    fn crim_sees_further_at_night_in_the_open() {
+        let mut game_world = GameWorld::default();
+        for x in -3..=3 {
+            for z in -3..=3 {
+                game_world.blocks.insert((x, 0, z), BlockType::Stone.into());
+            }
+        }
+        game_world.blocks.insert((0, 3, 2), BlockType::Stone.into());
+        game_world.relight_all();
+        let day = TimeOfDay { time: 0.5, day: 0 };
+        let night = TimeOfDay { time: 0.0, day: 0 };
+
+        // Nothing lights the open ground at night but the night bonus still wins
+        let open = Vec3::new(0.5, 1.0, 0.5);
+        assert!(
+            crim_sight_range(&game_world, open, crim_vision_range(&night))
+                > crim_sight_range(&game_world, open, crim_vision_range(&day))
+        );
+
+        // Standing in the shade under a roof is what makes the player harder to spot
+        let shaded = Vec3::new(0.5, 2.0, 2.5);
+        assert!(crim_sight_range(&game_world, shaded, CRIM_VISION_RANGE) < crim_sight_range(&game_world, open, CRIM_VISION_RANGE));
+    }
+
+    #[test]
+    fn shelter_is_sealed_only_when_walled_in() {
+        // A one by two pocket with a floor, a roof and a dirt patch in one wall
+        let mut game_world = GameWorld::default();
+        for y in 1..=2 {
+            for side in [(1, y, 0), (-1, y, 0), (0, y, 1), (0, y, -1)] {
+                game_world.blocks.insert(side, BlockType::Stone.into());
+            }
+        }
+        game_world.blocks.insert((0, 0, 0), BlockType::Stone.into());
+        game_world.blocks.insert((0, 3, 0), BlockType::Stone.into());
+        game_world.blocks.insert((1, 2, 0), BlockType::Dirt.into());
+
+        let shelter = analyze_shelter(&game_world, (0, 1, 0));
+        assert!(shelter.sealed);
+        assert_eq!(shelter.volume, 2);
+        assert_eq!(shelter.weakest_wall, Some(((1, 2, 0), BlockType::Dirt)));
+
+        // Knock a hole in one side and the air leaks out
+        game_world.blocks.remove(&(-1, 1, 0));
+        let shelter = analyze_shelter(&game_world, (0, 1, 0));
+        assert!(!shelter.sealed);
+        assert_eq!(shelter.weakest_wall, None);
+    }
+
+    #[test]
+    fn torches_and_lanterns_are_not_walls() {
+        let mut game_world = GameWorld::default();
+        game_world.blocks.insert((0, 1, 0), BlockType::Torch.into());
+        game_world.blocks.insert((0, 2, 0), BlockType::Lantern.into());
+        assert!(!game_world.is_solid((0, 1, 0)));
+        assert!(!game_world.is_solid((0, 2, 0)));
+        assert_eq!(game_world.body_collision(Vec3::new(0.5, 1.0, 0.5)), None);
+        assert_eq!(raycast_blocks(&game_world, Vec3::new(-3.0, 1.5, 0.5), Vec3::new(3.0, 1.5, 0.5)), None);
+    }
+
+    #[test]
+    fn water_levels_flow_fall_and_drain() {
+        let mut game_world = GameWorld::default();
+        for x in -1..=9 {
+            game_world.blocks.insert((x, 0, 0), BlockType::Stone.into());
+        }
+        let water = |level| BlockState::from(BlockType::Water).with_level(level);
+
+        // A source stays put whatever is around it
+        game_world.blocks.insert((0, 1, 0), water(0));
+        assert_eq!(next_water_level(&game_world, (0, 1, 0)), Some(0));
+
+        // Flowing water thins out one level per block away from its source
+        assert_eq!(next_water_level(&game_world, (1, 1, 0)), Some(1));
+        game_world.blocks.insert((1, 1, 0), water(1));
+        assert_eq!(next_water_level(&game_world, (2, 1, 0)), Some(2));
+
+        // The thinnest water spreads no further
+        game_world.blocks.insert((5, 1, 0), water(FLUID_MAX_LEVEL));
+        assert_eq!(next_water_level(&game_world, (6, 1, 0)), None);
+
+        // Water pouring in from above is as full as flowing water gets
+        game_world.blocks.insert((8, 2, 0), water(4));
+        assert_eq!(next_water_level(&game_world, (8, 1, 0)), Some(1));
+
+        // Cut off from its source, flowing water dries up
+        game_world.blocks.remove(&(0, 1, 0));
+        assert_eq!(next_water_level(&game_world, (1, 1, 0)), None);
+    }
+
+    #[test]
+    fn sand_on_the_world_floor_stays_put() {
+        let mut game_world = GameWorld::default();
+        game_world.blocks.insert((0, 0, 0), BlockType::Sand.into());
+        game_world.blocks.insert((4, 2, 0), BlockType::Sand.into());
+        let mut block_ticks = BlockTicks::default();
+
+        // Something changes right next to both, only the sand hanging in the air gets ready to fall
+        block_ticks.notify_neighbours((1, 0, 0));
+        block_ticks.notify_neighbours((5, 2, 0));
+        for pos in std::mem::take(&mut block_ticks.neighbour_updates) {
+            on_neighbour_update(&game_world, &mut block_ticks, pos);
+        }
+        assert_eq!(block_ticks.scheduled_cells, HashSet::from([(4, 2, 0)]));
+
+        // Even when its tick comes round, the bottom layer has nowhere to fall to
+        let block_assets = BlockAssets {
+            cube_mesh: Handle::default(),
+            lit_meshes: Vec::new(),
+            lit_log_meshes: Vec::new(),
+            shaped_meshes: HashMap::new(),
+            materials: HashMap::from([(BlockType::Sand, Handle::default())]),
+            flowing_water: Handle::default(),
+        };
+        let mut world = World::new();
+        let mut redraw = HashSet::new();
+        on_scheduled_tick(&mut world.commands(), &block_assets, &mut game_world, &mut block_ticks, &mut redraw, (0, 0, 0));
+        world.flush();
+        assert_eq!(game_world.block_type((0, 0, 0)), Some(BlockType::Sand));
+        assert!(redraw.is_empty());
+        assert_eq!(world.query::<&FallingBlock>().iter(&world).count(), 0);
+    }
+
+    #[test]
+    fn tab_completes_commands_with_or_without_the_slash() {
+        let mut registry = CommandRegistry::default();
+        registry.register("give", "/give <block> [count]", |_| block_names());
+        registry.register("gamemode", "/gamemode <survival|creative|spectator>", no_completions);
+
+        assert_eq!(registry.completions("/gi"), vec!["give".to_string()]);
+        assert_eq!(registry.completions("gi"), vec!["give".to_string()]);
+        assert_eq!(complete_last_word("/gi", "give"), "/give");
+        assert_eq!(complete_last_word("gi", "give"), "give");
+        assert_eq!(complete_last_word("/", "g"), "/g");
+
+        // Arguments after the command keep everything before them
+        assert_eq!(complete_last_word("/give di", "dirt"), "/give dirt");
+        assert_eq!(complete_last_word("give ", "dirt"), "give dirt");
+    }
+
+    #[test]
+    fn unknown_save_lines_are_rejected() {
+        let path = std::env::temp_dir().join(format!("crimcraft-test-bad-{}.sav", std::process::id()));
+        std::fs::write(&path, "flow 0 0 0 3\n").unwrap();
+        let loaded = SaveData::read(&path);
+        let _ = std::fs::remove_file(&path);
+        assert!(loaded.is_err());
+    }
+}